@@ -61,6 +61,8 @@ const BENCH_POSITIONS: [&'static str; 50] = [
 pub fn run_bench() {
     let start_time = Instant::now();
     let mut nodes = 0;
+    let mut visits = 0;
+    let mut evals = 0;
 
     let mut searcher = MCTS::new();
     let mut limits = SearchLimits::new();
@@ -70,11 +72,14 @@ pub fn run_bench() {
 
     for fen in BENCH_POSITIONS {
         position.parse_fen(fen);
-        let results = searcher.run(limits, false, &position);
+        let results = searcher.run(&limits, false, &position);
         nodes += results.nodes;
+        visits += results.visits as u64;
+        evals += results.evals as u64;
     }
 
     let elapsed = start_time.elapsed().as_secs_f64();
     let nps = nodes as f64 / elapsed;
+    println!("{} visits {} evals", visits, evals);
     println!("{} nodes {} nps", nodes, nps.round());
 }
@@ -18,6 +18,20 @@ pub struct Board {
     zkey: ZobristKey,
 }
 
+// what make_move overwrites, so unmake_move can put it back without recomputing anything
+#[derive(Clone, Copy)]
+pub struct Undo {
+    captured: Option<Piece>,
+    checkers: Bitboard,
+    diag_pinned: Bitboard,
+    hv_pinned: Bitboard,
+    check_squares: [Bitboard; 5],
+    castling_rooks: CastlingRooks,
+    ep_square: Option<Square>,
+    half_move_clock: u8,
+    zkey: ZobristKey,
+}
+
 impl Board {
     const STARTPOS_FEN: &'static str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
 
@@ -241,7 +255,19 @@ impl Board {
         fen
     }
 
-    pub fn make_move(&mut self, mv: Move) {
+    pub fn make_move(&mut self, mv: Move) -> Undo {
+        let mut undo = Undo {
+            captured: None,
+            checkers: self.checkers,
+            diag_pinned: self.diag_pinned,
+            hv_pinned: self.hv_pinned,
+            check_squares: self.check_squares,
+            castling_rooks: self.castling_rooks,
+            ep_square: self.ep_square,
+            half_move_clock: self.half_move_clock,
+            zkey: self.zkey,
+        };
+
         if let Some(ep_square) = self.ep_square() {
             self.zkey.toggle_ep_square(ep_square);
         }
@@ -316,6 +342,59 @@ impl Board {
         self.zkey.toggle_stm();
 
         self.update_check_info();
+
+        undo.captured = captured;
+        undo
+    }
+
+    // mv and undo must come from the last make_move on this board
+    pub fn unmake_move(&mut self, mv: Move, undo: &Undo) {
+        self.stm = !self.stm;
+
+        let from = mv.from_sq();
+        let to = mv.to_sq();
+        match mv.kind() {
+            MoveKind::None => {
+                self.move_piece(to, from);
+                if let Some(captured) = undo.captured {
+                    self.add_piece(to, captured);
+                }
+            }
+            MoveKind::Promotion => {
+                self.remove_piece(to);
+                self.add_piece(from, Piece::new(self.stm, PieceType::Pawn));
+                if let Some(captured) = undo.captured {
+                    self.add_piece(to, captured);
+                }
+            }
+            MoveKind::Enpassant => {
+                self.move_piece(to, from);
+                let cap_sq = if self.stm == Color::White {
+                    to - 8
+                } else {
+                    to + 8
+                };
+                self.add_piece(cap_sq, undo.captured.unwrap());
+            }
+            MoveKind::Castle => {
+                // the king and rook squares can overlap in chess960 so both are lifted first
+                let king_side = to > from;
+                self.remove_piece(CastlingRooks::king_to(king_side, self.stm));
+                self.remove_piece(CastlingRooks::rook_to(king_side, self.stm));
+                self.add_piece(from, Piece::new(self.stm, PieceType::King));
+                self.add_piece(to, Piece::new(self.stm, PieceType::Rook));
+            }
+        }
+
+        self.checkers = undo.checkers;
+        self.diag_pinned = undo.diag_pinned;
+        self.hv_pinned = undo.hv_pinned;
+        self.check_squares = undo.check_squares;
+        self.castling_rooks = undo.castling_rooks;
+        self.ep_square = undo.ep_square;
+        self.half_move_clock = undo.half_move_clock;
+        // the piece updates above toggled the key as well, restoring it undoes them
+        self.zkey = undo.zkey;
     }
 
     // doesn't handle castling or discovered check
@@ -350,10 +429,6 @@ impl Board {
         self.colors(piece.color()) & self.pieces(piece.piece_type())
     }
 
-    pub fn piece_count(&self, c: Color, pt: PieceType) -> i32 {
-        self.colored_pieces(Piece::new(c, pt)).popcount() as i32
-    }
-
     pub fn king_sq(&self, color: Color) -> Square {
         self.colored_pieces(Piece::new(color, PieceType::King))
             .lsb()
@@ -53,6 +53,14 @@ impl Move {
         )
     }
 
+    pub const fn from_raw(data: u16) -> Self {
+        Self { data }
+    }
+
+    pub const fn raw(&self) -> u16 {
+        self.data
+    }
+
     pub const fn from_sq(&self) -> Square {
         Square::from_raw((self.data & 63) as u8)
     }
@@ -98,6 +106,9 @@ impl FromStr for Move {
     type Err = MoveParseErr;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() < 4 || !s.is_ascii() {
+            return Err(MoveParseErr);
+        }
         let (from_str, rest) = s.split_at(2);
         let (to_str, promo) = rest.split_at(2);
 
@@ -3,10 +3,11 @@ pub mod board;
 pub mod castling_rooks;
 pub mod chess_move;
 pub mod movegen;
+pub mod notation;
 pub mod see;
 pub mod zobrist;
 
-pub use board::Board;
+pub use board::{Board, Undo};
 pub use castling_rooks::{CastlingRooks, RookPair};
 pub use chess_move::{Move, MoveKind};
 pub use zobrist::ZobristKey;
@@ -4,7 +4,31 @@ use arrayvec::ArrayVec;
 
 pub type MoveList = ArrayVec<Move, 256>;
 
+// every legal move
 pub fn movegen(board: &Board, moves: &mut MoveList) {
+    gen_moves::<true, true>(board, moves);
+}
+
+// legal captures, en passant and promotions
+pub fn movegen_noisy(board: &Board, moves: &mut MoveList) {
+    gen_moves::<true, false>(board, moves);
+}
+
+// legal moves that are not noisy, castling included
+pub fn movegen_quiet(board: &Board, moves: &mut MoveList) {
+    gen_moves::<false, true>(board, moves);
+}
+
+fn gen_moves<const NOISY: bool, const QUIET: bool>(board: &Board, moves: &mut MoveList) {
+    // squares pieces other than pawns may move to in this mode
+    let targets = if NOISY && QUIET {
+        Bitboard::ALL
+    } else if NOISY {
+        board.colors(!board.stm())
+    } else {
+        !board.occ()
+    };
+
     let checkers = board.checkers();
     if !checkers.multiple() {
         let move_mask = !board.colors(board.stm())
@@ -13,16 +37,20 @@ pub fn movegen(board: &Board, moves: &mut MoveList) {
             } else {
                 Bitboard::ALL
             };
-        gen_pawn_moves(board, move_mask, moves);
-        gen_knight_moves(board, move_mask, moves);
-        gen_bishop_moves(board, move_mask, moves);
-        gen_rook_moves(board, move_mask, moves);
-        gen_queen_moves(board, move_mask, moves);
+        gen_pawn_moves::<NOISY, QUIET>(board, move_mask, moves);
+        gen_knight_moves(board, move_mask & targets, moves);
+        gen_bishop_moves(board, move_mask & targets, moves);
+        gen_rook_moves(board, move_mask & targets, moves);
+        gen_queen_moves(board, move_mask & targets, moves);
     }
-    gen_king_moves(board, moves);
+    gen_king_moves::<QUIET>(board, targets, moves);
 }
 
-fn gen_pawn_moves(board: &Board, move_mask: Bitboard, moves: &mut MoveList) {
+fn gen_pawn_moves<const NOISY: bool, const QUIET: bool>(
+    board: &Board,
+    move_mask: Bitboard,
+    moves: &mut MoveList,
+) {
     let eighth_rank = if board.stm() == Color::White {
         Bitboard::RANK_8
     } else {
@@ -64,6 +92,15 @@ fn gen_pawn_moves(board: &Board, move_mask: Bitboard, moves: &mut MoveList) {
     let mut promo_pushes = pushes & eighth_rank;
     let mut non_promo_pushes = pushes ^ promo_pushes;
 
+    // promotions count as noisy even without a capture
+    if !NOISY {
+        promo_pushes = Bitboard::NONE;
+    }
+    if !QUIET {
+        non_promo_pushes = Bitboard::NONE;
+        double_pushes = Bitboard::NONE;
+    }
+
     while promo_pushes.any() {
         let sq = promo_pushes.poplsb();
         moves.push(Move::promo(sq - push_offset, sq, PieceType::Knight));
@@ -82,6 +119,10 @@ fn gen_pawn_moves(board: &Board, move_mask: Bitboard, moves: &mut MoveList) {
         moves.push(Move::normal(sq - push_offset * 2, sq));
     }
 
+    if !NOISY {
+        return;
+    }
+
     let mut west_caps = board.colors(!board.stm())
         & attacks::pawn_west_attacks_bb(
             board.stm(),
@@ -212,10 +253,10 @@ fn gen_queen_moves(board: &Board, move_mask: Bitboard, moves: &mut MoveList) {
     }
 }
 
-fn gen_king_moves(board: &Board, moves: &mut MoveList) {
+fn gen_king_moves<const QUIET: bool>(board: &Board, targets: Bitboard, moves: &mut MoveList) {
     let sq = board.king_sq(board.stm());
     let mut attacks = attacks::king_attacks(sq);
-    attacks &= !board.colors(board.stm());
+    attacks &= !board.colors(board.stm()) & targets;
     while attacks.any() {
         let dst = attacks.poplsb();
         if !board.attacked_by(dst, !board.stm()) {
@@ -223,7 +264,7 @@ fn gen_king_moves(board: &Board, moves: &mut MoveList) {
         }
     }
 
-    if board.checkers().any() {
+    if !QUIET || board.checkers().any() {
         return;
     }
 
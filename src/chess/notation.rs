@@ -0,0 +1,294 @@
+use crate::{
+    chess::{
+        movegen::{movegen, MoveList},
+        Board, Move, MoveKind,
+    },
+    types::{Color, Piece, PieceType, Square},
+};
+
+fn piece_char(pt: PieceType) -> char {
+    Piece::new(Color::White, pt).char_repr()
+}
+
+fn piece_from_char(c: char) -> Option<PieceType> {
+    match c {
+        'N' => Some(PieceType::Knight),
+        'B' => Some(PieceType::Bishop),
+        'R' => Some(PieceType::Rook),
+        'Q' => Some(PieceType::Queen),
+        'K' => Some(PieceType::King),
+        _ => None,
+    }
+}
+
+fn moving_piece(board: &Board, mv: Move) -> PieceType {
+    board.piece_at(mv.from_sq()).unwrap().piece_type()
+}
+
+fn is_capture(board: &Board, mv: Move) -> bool {
+    mv.kind() == MoveKind::Enpassant
+        || (mv.kind() != MoveKind::Castle && board.piece_at(mv.to_sq()).is_some())
+}
+
+// standard chess uses the king's destination for castling rather than king takes rook
+pub fn move_to_uci(board: &Board, mv: Move) -> String {
+    if mv.kind() == MoveKind::Castle && !board.castling_rooks().frc {
+        let file = if mv.to_sq() > mv.from_sq() { 6 } else { 2 };
+        let to = Square::from_rank_file(mv.from_sq().rank(), file);
+        return format!("{}{}", mv.from_sq(), to);
+    }
+    mv.to_string()
+}
+
+// accepts both king to destination and king takes rook castling
+pub fn move_from_uci(board: &Board, uci: &str) -> Option<Move> {
+    let parsed = uci.trim().parse::<Move>().ok()?;
+
+    let mut moves = MoveList::new();
+    movegen(board, &mut moves);
+    moves.into_iter().find(|candidate| {
+        let normal = candidate.from_sq() == parsed.from_sq()
+            && candidate.to_sq() == parsed.to_sq()
+            && (candidate.kind() != MoveKind::Promotion
+                || candidate.promo_piece() == parsed.promo_piece());
+        let castle = candidate.from_sq() == parsed.from_sq()
+            && candidate.kind() == MoveKind::Castle
+            && (candidate.to_sq() > candidate.from_sq()) == (parsed.to_sq() > parsed.from_sq());
+        normal || castle
+    })
+}
+
+fn check_suffix(board: &Board, mv: Move) -> &'static str {
+    let mut next = board.clone();
+    next.make_move(mv);
+    if !next.checkers().any() {
+        return "";
+    }
+
+    let mut moves = MoveList::new();
+    movegen(&next, &mut moves);
+    if moves.is_empty() {
+        "#"
+    } else {
+        "+"
+    }
+}
+
+fn castle_str(mv: Move) -> &'static str {
+    if mv.to_sq() > mv.from_sq() {
+        "O-O"
+    } else {
+        "O-O-O"
+    }
+}
+
+fn castle_side(notation: &str) -> Option<bool> {
+    match notation {
+        "O-O" | "0-0" => Some(true),
+        "O-O-O" | "0-0-0" => Some(false),
+        _ => None,
+    }
+}
+
+fn find_castle(moves: MoveList, king_side: bool) -> Option<Move> {
+    moves
+        .into_iter()
+        .find(|mv| mv.kind() == MoveKind::Castle && (mv.to_sq() > mv.from_sq()) == king_side)
+}
+
+fn strip_annotations(notation: &str) -> &str {
+    notation.trim().trim_end_matches(['+', '#', '!', '?'])
+}
+
+// the move must be legal in the given position
+pub fn move_to_san(board: &Board, mv: Move) -> String {
+    let mut san = String::new();
+    let pt = moving_piece(board, mv);
+
+    if mv.kind() == MoveKind::Castle {
+        san += castle_str(mv);
+    } else if pt == PieceType::Pawn {
+        if is_capture(board, mv) {
+            san.push((b'a' + mv.from_sq().file()) as char);
+            san.push('x');
+        }
+        san += mv.to_sq().to_string().as_str();
+        if mv.kind() == MoveKind::Promotion {
+            san.push('=');
+            san.push(piece_char(mv.promo_piece()));
+        }
+    } else {
+        san.push(piece_char(pt));
+
+        // other pieces of the same type that can reach the same square
+        let mut moves = MoveList::new();
+        movegen(board, &mut moves);
+        let ambiguous = moves
+            .iter()
+            .filter(|other| {
+                **other != mv
+                    && other.kind() != MoveKind::Castle
+                    && other.to_sq() == mv.to_sq()
+                    && moving_piece(board, **other) == pt
+            })
+            .collect::<Vec<_>>();
+
+        if !ambiguous.is_empty() {
+            let same_file = ambiguous
+                .iter()
+                .any(|other| other.from_sq().file() == mv.from_sq().file());
+            let same_rank = ambiguous
+                .iter()
+                .any(|other| other.from_sq().rank() == mv.from_sq().rank());
+            if !same_file {
+                san.push((b'a' + mv.from_sq().file()) as char);
+            } else if !same_rank {
+                san.push((b'1' + mv.from_sq().rank()) as char);
+            } else {
+                san += mv.from_sq().to_string().as_str();
+            }
+        }
+
+        if is_capture(board, mv) {
+            san.push('x');
+        }
+        san += mv.to_sq().to_string().as_str();
+    }
+
+    san += check_suffix(board, mv);
+    san
+}
+
+// lenient parser, accepts missing or redundant disambiguation, annotations and 0-0 style castling
+pub fn move_from_san(board: &Board, san: &str) -> Option<Move> {
+    let san = strip_annotations(san);
+
+    let mut moves = MoveList::new();
+    movegen(board, &mut moves);
+
+    if let Some(king_side) = castle_side(san) {
+        return find_castle(moves, king_side);
+    }
+
+    let mut chars = san
+        .chars()
+        .filter(|c| *c != 'x' && *c != '-')
+        .collect::<Vec<char>>();
+
+    let pt = match chars.first().copied().and_then(piece_from_char) {
+        Some(pt) => {
+            chars.remove(0);
+            pt
+        }
+        None => PieceType::Pawn,
+    };
+
+    let mut promo = None;
+    if pt == PieceType::Pawn {
+        if let Some(promo_pt) = chars.last().copied().and_then(piece_from_char) {
+            promo = Some(promo_pt);
+            chars.pop();
+            if chars.last() == Some(&'=') {
+                chars.pop();
+            }
+        }
+    }
+
+    if chars.len() < 2 {
+        return None;
+    }
+    let to_str = chars[chars.len() - 2..].iter().collect::<String>();
+    let to = to_str.parse::<Square>().ok()?;
+
+    let mut from_file = None;
+    let mut from_rank = None;
+    for c in &chars[..chars.len() - 2] {
+        match c {
+            'a'..='h' => from_file = Some(*c as u8 - b'a'),
+            '1'..='8' => from_rank = Some(*c as u8 - b'1'),
+            _ => return None,
+        }
+    }
+
+    let mut candidates = moves.into_iter().filter(|mv| {
+        mv.kind() != MoveKind::Castle
+            && mv.to_sq() == to
+            && moving_piece(board, *mv) == pt
+            && from_file.is_none_or(|file| mv.from_sq().file() == file)
+            && from_rank.is_none_or(|rank| mv.from_sq().rank() == rank)
+            && (mv.kind() == MoveKind::Promotion) == promo.is_some()
+            && promo.is_none_or(|promo| mv.promo_piece() == promo)
+    });
+
+    let result = candidates.next()?;
+    if candidates.next().is_some() {
+        return None;
+    }
+    Some(result)
+}
+
+// long algebraic notation, Ng1-f3, e7xd8=Q+ or O-O
+pub fn move_to_lan(board: &Board, mv: Move) -> String {
+    let mut lan = String::new();
+    if mv.kind() == MoveKind::Castle {
+        lan += castle_str(mv);
+    } else {
+        let pt = moving_piece(board, mv);
+        if pt != PieceType::Pawn {
+            lan.push(piece_char(pt));
+        }
+        lan += mv.from_sq().to_string().as_str();
+        lan.push(if is_capture(board, mv) { 'x' } else { '-' });
+        lan += mv.to_sq().to_string().as_str();
+        if mv.kind() == MoveKind::Promotion {
+            lan.push('=');
+            lan.push(piece_char(mv.promo_piece()));
+        }
+    }
+    lan += check_suffix(board, mv);
+    lan
+}
+
+pub fn move_from_lan(board: &Board, lan: &str) -> Option<Move> {
+    let lan = strip_annotations(lan);
+
+    let mut moves = MoveList::new();
+    movegen(board, &mut moves);
+
+    if let Some(king_side) = castle_side(lan) {
+        return find_castle(moves, king_side);
+    }
+
+    let (pt, rest) = match lan.chars().next().and_then(piece_from_char) {
+        Some(pt) => (pt, &lan[1..]),
+        None => (PieceType::Pawn, lan),
+    };
+    if rest.len() < 5 || !rest.is_ascii() {
+        return None;
+    }
+    let from = rest[0..2].parse::<Square>().ok()?;
+    if !matches!(&rest[2..3], "-" | "x") {
+        return None;
+    }
+    let to = rest[3..5].parse::<Square>().ok()?;
+    let promo = match rest[5..].strip_prefix('=').unwrap_or(&rest[5..]) {
+        "" => None,
+        promo_str => Some(piece_from_char(promo_str.chars().next()?)?),
+    };
+
+    moves.into_iter().find(|mv| {
+        mv.kind() != MoveKind::Castle
+            && mv.from_sq() == from
+            && mv.to_sq() == to
+            && moving_piece(board, *mv) == pt
+            && (mv.kind() == MoveKind::Promotion) == promo.is_some()
+            && promo.is_none_or(|promo| mv.promo_piece() == promo)
+    })
+}
+
+// tries uci, then san, then lan
+pub fn parse_move(board: &Board, notation: &str) -> Option<Move> {
+    move_from_uci(board, notation)
+        .or_else(|| move_from_san(board, notation))
+        .or_else(|| move_from_lan(board, notation))
+}
@@ -82,6 +82,10 @@ impl ZobristKey {
         self.0 ^= ZOBRIST_KEYS.enpassant[ep_square.file() as usize];
     }
 
+    pub fn from_value(value: u64) -> Self {
+        Self(value)
+    }
+
     pub fn value(self) -> u64 {
         self.0
     }
@@ -1,25 +1,24 @@
 use std::{fs::File, io::Write, thread, time::Instant};
 
-use rand::Rng;
 use rand_core::{RngCore, SeedableRng};
 use rand_xorshift::XorShiftRng;
 
 use crate::{
-    chess::{
-        movegen::{self, MoveList},
-        Move,
-    },
-    position::Position,
-    score::{GameResult, Score},
+    chess::{Board, Move},
+    pgn::{self, PgnGame},
+    score::GameResult,
     search::{SearchLimits, MCTS},
+    selfplay::{game_result, init_opening, Outcome},
     types::Color,
 };
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone)]
 struct DataPoint {
     fen: String,
     visit_dist: Vec<(Move, f32)>,
     score: f32,
+    best_move: Move,
+    eval_cp: i32,
 }
 
 #[derive(Debug, Clone, Copy, Default)]
@@ -38,15 +37,28 @@ impl WDL {
             Self::BlackWin => 0.0,
         }
     }
+
+    fn outcome(self) -> Outcome {
+        match self {
+            Self::WhiteWin => Outcome::WhiteWin,
+            Self::Draw => Outcome::Draw,
+            Self::BlackWin => Outcome::BlackWin,
+        }
+    }
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Clone)]
 struct Game {
+    opening: Board,
     points: Vec<DataPoint>,
     wdl: WDL,
+    // search nodes over every move, counted the same way bench does
+    nodes: u64,
 }
 
 const NUM_THREADS: i32 = 8;
+// a playout limit keeps the visit distributions comparable however deep the tree gets
+const VISITS_PER_MOVE: i32 = 20000;
 
 pub fn run_datagen() {
     let mut handles = Vec::new();
@@ -71,10 +83,14 @@ pub fn datagen_thread(thread_id: i32) {
     let policy_filename = format!("datagen{}.policy.txt", thread_id);
     let mut policy_file = File::create(policy_filename).expect("Unable to create policy data file");
 
+    let pgn_filename = format!("datagen{}.pgn", thread_id);
+    let mut pgn_file = File::create(pgn_filename).expect("Unable to create pgn file");
+
     let mut rng = XorShiftRng::seed_from_u64(seed);
     let mut games = 0;
     let mut positions = 0;
     let mut total_positions = 0;
+    let mut nodes = 0;
     let mut start_time = Instant::now();
     loop {
         let game = run_game(&mut search, &mut rng);
@@ -88,19 +104,28 @@ pub fn datagen_thread(thread_id: i32) {
             .expect("Unable to write policy data");
 
         games += 1;
+        pgn_file
+            .write_all(to_pgn(&game, thread_id, games).as_bytes())
+            .expect("Unable to write pgn");
+
         positions += num_positions;
         total_positions += num_positions;
+        nodes += game.nodes;
         if games % 32 == 0 {
+            let elapsed = start_time.elapsed().as_secs_f64();
             println!(
-                "Thread {} wrote {} total games and {} total positions. {} positions in last 32 games in {} seconds",
+                "Thread {} wrote {} total games and {} total positions. {} positions in last 32 games in {} seconds, {} nodes {} nps",
                 thread_id,
                 games,
                 total_positions,
                 positions,
-                start_time.elapsed().as_secs_f32()
+                elapsed as f32,
+                nodes,
+                (nodes as f64 / elapsed).round()
             );
             start_time = Instant::now();
             positions = 0;
+            nodes = 0;
         }
     }
 }
@@ -123,56 +148,37 @@ fn serialize(game: &Game) -> (i32, String, String) {
     (num_positions, value, policy)
 }
 
-fn game_result(pos: &Position) -> GameResult {
-    let mut moves = MoveList::new();
-    movegen::movegen(pos.board(), &mut moves);
-
-    if moves.len() == 0 {
-        if pos.board().checkers().any() {
-            GameResult::Mated
-        } else {
-            GameResult::Drawn
-        }
-    } else if pos.is_drawn(0) {
-        GameResult::Drawn
-    } else {
-        GameResult::NonTerminal
-    }
-}
-
-fn init_opening(rng: &mut XorShiftRng) -> Position {
-    'new_opening: loop {
-        let mut pos = Position::new();
-        for _ in 0..8 {
-            let mut moves = MoveList::new();
-            movegen::movegen(pos.board(), &mut moves);
-
-            let idx = rng.random_range(0..moves.len());
-            pos.make_move(moves[idx]);
-            if game_result(&pos) != GameResult::NonTerminal {
-                continue 'new_opening;
-            }
-        }
-        return pos;
+fn to_pgn(game: &Game, thread_id: i32, game_idx: i32) -> String {
+    let mut pgn = PgnGame::new(&game.opening);
+    pgn.add_tag("Event", "Aquarii datagen");
+    pgn.add_tag("Round", format!("{}.{}", thread_id, game_idx).as_str());
+    pgn.add_tag("White", "Aquarii");
+    pgn.add_tag("Black", "Aquarii");
+    pgn.add_tag("Visits", VISITS_PER_MOVE.to_string().as_str());
+    for pt in &game.points {
+        pgn.add_move(pt.best_move, Some(pgn::eval_comment(pt.eval_cp)));
     }
+    pgn.set_result(game.wdl.outcome());
+    pgn.to_pgn()
 }
 
 fn run_game(search: &mut MCTS, rng: &mut XorShiftRng) -> Game {
     let mut limits = SearchLimits::new();
-    limits.max_nodes = 20000;
+    limits.max_visits = VISITS_PER_MOVE;
 
     let mut pos = init_opening(rng);
 
-    let mut game = Game::default();
+    let mut game = Game {
+        opening: pos.board().clone(),
+        points: Vec::new(),
+        wdl: WDL::default(),
+        nodes: 0,
+    };
 
     loop {
-        let results = search.run(limits, false, &pos);
-        let mut datapt_score = match results.score {
-            Score::Win(_) => 1.0,
-            Score::Draw => 0.5,
-            Score::Loss(_) => 0.0,
-            Score::Normal(wdl) => wdl,
-        };
+        let results = search.run(&limits, false, &pos);
+        game.nodes += results.nodes;
+        let mut datapt_score = results.score.win_prob();
         if pos.board().stm() == Color::Black {
             datapt_score = 1.0 - datapt_score;
         }
@@ -181,6 +187,8 @@ fn run_game(search: &mut MCTS, rng: &mut XorShiftRng) -> Game {
             fen: pos.board().to_fen(),
             visit_dist: results.visit_dist,
             score: datapt_score,
+            best_move: results.best_move,
+            eval_cp: results.score.cp(),
         });
 
         pos.make_move(results.best_move);
@@ -696,7 +696,8 @@ fn evaluate_pawns<Params: EvalValues>(
     eval
 }
 
-pub fn eval_impl<Params: EvalValues>(board: &Board) -> Params::ScoreType {
+// untapered eval from the side to move's perspective along with the game phase, without tempo
+pub fn eval_pair_impl<Params: EvalValues>(board: &Board) -> (Params::ScorePairType, i32) {
     let stm = board.stm();
     let mut eval = Params::ScorePairType::default();
     for pt in [
@@ -759,7 +760,12 @@ pub fn eval_impl<Params: EvalValues>(board: &Board) -> Params::ScoreType {
         + board.pieces(PieceType::Bishop).popcount()
         + board.pieces(PieceType::Knight).popcount()) as i32;
 
-    (eval.mg() * phase.min(24) + eval.eg() * (24 - phase.min(24))) / 24 + Params::tempo()
+    (eval, phase.min(24))
+}
+
+pub fn eval_impl<Params: EvalValues>(board: &Board) -> Params::ScoreType {
+    let (eval, phase) = eval_pair_impl::<Params>(board);
+    (eval.mg() * phase + eval.eg() * (24 - phase)) / 24 + Params::tempo()
 }
 
 pub fn eval(board: &Board) -> i32 {
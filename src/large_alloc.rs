@@ -0,0 +1,159 @@
+use std::{
+    alloc::{self, Layout},
+    fmt,
+    ops::{Deref, DerefMut},
+    ptr::NonNull,
+    slice,
+};
+
+/// Types that can be used straight out of a fresh zeroed allocation.
+///
+/// # Safety
+///
+/// The all zero bit pattern must be a valid value of the type.
+pub unsafe trait Zeroable {}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum AllocStrategy {
+    HugePages,
+    Mmap,
+    Heap,
+}
+
+impl fmt::Display for AllocStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::HugePages => write!(f, "transparent huge pages"),
+            Self::Mmap => write!(f, "regular pages"),
+            Self::Heap => write!(f, "heap"),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use std::ffi::c_void;
+
+    pub const PROT_READ: i32 = 1;
+    pub const PROT_WRITE: i32 = 2;
+    pub const MAP_PRIVATE: i32 = 2;
+    pub const MAP_ANONYMOUS: i32 = 0x20;
+    pub const MADV_HUGEPAGE: i32 = 14;
+
+    extern "C" {
+        pub fn mmap(
+            addr: *mut c_void,
+            len: usize,
+            prot: i32,
+            flags: i32,
+            fd: i32,
+            offset: i64,
+        ) -> *mut c_void;
+        pub fn munmap(addr: *mut c_void, len: usize) -> i32;
+        pub fn madvise(addr: *mut c_void, len: usize, advice: i32) -> i32;
+    }
+}
+
+// a fixed size zeroed array for things like the search tree, the os hands out zeroed pages
+// lazily so allocating is quick no matter the size and memory is only committed once written,
+// which also keeps it local to the thread that uses it
+pub struct LargeBuffer<T: Zeroable> {
+    ptr: NonNull<T>,
+    len: usize,
+    strategy: AllocStrategy,
+}
+
+// the buffer owns its elements like a Vec does
+unsafe impl<T: Zeroable + Send> Send for LargeBuffer<T> {}
+unsafe impl<T: Zeroable + Sync> Sync for LargeBuffer<T> {}
+
+impl<T: Zeroable> LargeBuffer<T> {
+    pub fn new(len: usize, huge_pages: bool) -> Self {
+        let bytes = len * std::mem::size_of::<T>();
+        if bytes == 0 {
+            return Self {
+                ptr: NonNull::dangling(),
+                len,
+                strategy: AllocStrategy::Heap,
+            };
+        }
+
+        #[cfg(target_os = "linux")]
+        {
+            let ptr = unsafe {
+                sys::mmap(
+                    std::ptr::null_mut(),
+                    bytes,
+                    sys::PROT_READ | sys::PROT_WRITE,
+                    sys::MAP_PRIVATE | sys::MAP_ANONYMOUS,
+                    -1,
+                    0,
+                )
+            };
+            // mmap returns MAP_FAILED, all bits set, on failure
+            if ptr as isize != -1 {
+                let strategy =
+                    if huge_pages && unsafe { sys::madvise(ptr, bytes, sys::MADV_HUGEPAGE) } == 0 {
+                        AllocStrategy::HugePages
+                    } else {
+                        AllocStrategy::Mmap
+                    };
+                return Self {
+                    ptr: NonNull::new(ptr as *mut T).unwrap(),
+                    len,
+                    strategy,
+                };
+            }
+        }
+        #[cfg(not(target_os = "linux"))]
+        let _ = huge_pages;
+
+        // large calloc style allocations are usually lazily zeroed by the os as well
+        let ptr = unsafe { alloc::alloc_zeroed(Self::layout(len)) };
+        let Some(ptr) = NonNull::new(ptr as *mut T) else {
+            alloc::handle_alloc_error(Self::layout(len));
+        };
+        Self {
+            ptr,
+            len,
+            strategy: AllocStrategy::Heap,
+        }
+    }
+
+    pub fn strategy(&self) -> AllocStrategy {
+        self.strategy
+    }
+
+    fn layout(len: usize) -> Layout {
+        Layout::array::<T>(len).expect("Allocation too large")
+    }
+}
+
+impl<T: Zeroable> Drop for LargeBuffer<T> {
+    fn drop(&mut self) {
+        let bytes = self.len * std::mem::size_of::<T>();
+        if bytes == 0 {
+            return;
+        }
+        match self.strategy {
+            #[cfg(target_os = "linux")]
+            AllocStrategy::HugePages | AllocStrategy::Mmap => unsafe {
+                sys::munmap(self.ptr.as_ptr() as *mut std::ffi::c_void, bytes);
+            },
+            _ => unsafe { alloc::dealloc(self.ptr.as_ptr() as *mut u8, Self::layout(self.len)) },
+        }
+    }
+}
+
+impl<T: Zeroable> Deref for LargeBuffer<T> {
+    type Target = [T];
+    fn deref(&self) -> &Self::Target {
+        unsafe { slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl<T: Zeroable> DerefMut for LargeBuffer<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
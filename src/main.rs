@@ -1,50 +1,39 @@
-use std::{env, io, str::SplitWhitespace};
+use std::{
+    env, io,
+    str::SplitWhitespace,
+    sync::{atomic::Ordering, mpsc},
+    thread,
+};
 
 mod bench;
 mod chess;
 mod datagen;
 mod eval;
+mod large_alloc;
+mod match_runner;
 mod perft;
+mod pgn;
 mod policy;
 mod position;
+mod qsearch;
 mod score;
 mod search;
+mod selfplay;
+mod spsa;
 mod tree;
+mod tree_view;
 mod tune;
 mod types;
 
 use bench::run_bench;
 use chess::{
     movegen::{movegen, MoveList},
-    Board, Move, MoveKind,
+    notation, Move,
 };
 use position::Position;
-use search::SearchLimits;
+use search::{FpuMode, MoveSelection, SearchLimits};
 use types::Color;
 
-fn move_from_str(board: &Board, mv_str: &str) -> Option<Move> {
-    let parsed = mv_str.parse::<Move>().unwrap_or(Move::NULL);
-
-    let mut moves = MoveList::new();
-    movegen(board, &mut moves);
-    for candidate in moves {
-        if candidate.from_sq() == parsed.from_sq()
-            && candidate.to_sq() == parsed.to_sq()
-            && (candidate.kind() != MoveKind::Promotion
-                || candidate.promo_piece() == parsed.promo_piece())
-        {
-            return Some(candidate);
-        }
-        if candidate.from_sq() == parsed.from_sq()
-            && candidate.kind() == MoveKind::Castle
-            && (candidate.to_sq() > candidate.from_sq()) == (parsed.to_sq() > parsed.from_sq())
-        {
-            return Some(candidate);
-        }
-    }
-    None
-}
-
 fn parse_position(tokens: &mut SplitWhitespace, position: &mut Position) {
     match tokens.next() {
         Some("fen") => {
@@ -65,7 +54,8 @@ fn parse_position(tokens: &mut SplitWhitespace, position: &mut Position) {
 
     if tokens.next() == Some("moves") {
         while let Some(mv_str) = tokens.next() {
-            let Some(mv) = move_from_str(position.board(), mv_str) else {
+            // san and lan are accepted too, which is handy when typing positions in by hand
+            let Some(mv) = notation::parse_move(position.board(), mv_str) else {
                 println!("invalid move {}", mv_str);
                 return;
             };
@@ -86,6 +76,16 @@ fn main() {
         return;
     }
 
+    if args.len() >= 2 && args[1] == "match" {
+        match_runner::run_match(&args[2..args.len()]);
+        return;
+    }
+
+    if args.len() >= 2 && args[1] == "spsa" {
+        spsa::run_spsa(&args[2..args.len()]);
+        return;
+    }
+
     if args.len() >= 2 && args[1] == "tunepolicy" {
         tune::policy::main(&args[2..args.len()]);
         return;
@@ -96,15 +96,60 @@ fn main() {
         return;
     }
 
+    if args.len() >= 2 && args[1] == "extract" {
+        tune::extract::main(&args[2..args.len()]);
+        return;
+    }
+
+    if args.len() >= 2 && args[1] == "evaltrace" {
+        tune::eval::evaltrace_main(&args[2..args.len()]);
+        return;
+    }
+
+    if args.len() >= 2 && args[1] == "perft" {
+        perft::run_perft_tests();
+        return;
+    }
+
+    if args.len() >= 2 && args[1] == "evalstats" {
+        tune::eval::evalstats_main(&args[2..args.len()]);
+        return;
+    }
+
     let mut pos = Position::new();
     let mut searcher = search::MCTS::new();
+    println!("info string {}", searcher.hash_info());
 
-    loop {
+    // input is read on its own thread so stop and ponderhit reach a running search
+    let (sender, receiver) = mpsc::channel();
+    let signals = searcher.signals();
+    thread::spawn(move || loop {
         let mut cmd = String::new();
-
-        io::stdin()
+        let bytes = io::stdin()
             .read_line(&mut cmd)
             .expect("Failed to read line");
+        if bytes == 0 {
+            cmd = "quit".to_owned();
+        }
+        match cmd.split_whitespace().next() {
+            Some("go") => signals.start(),
+            Some("stop") | Some("quit") => signals.stop.store(true, Ordering::Relaxed),
+            Some("ponderhit") => signals.ponderhit.store(true, Ordering::Relaxed),
+            // the main thread is busy searching, uci still wants an answer straight away
+            Some("isready") if signals.searching.load(Ordering::Relaxed) => {
+                println!("readyok");
+                continue;
+            }
+            _ => {}
+        }
+        let quit = bytes == 0 || cmd.trim() == "quit";
+        if sender.send(cmd).is_err() || quit {
+            return;
+        }
+    });
+
+    loop {
+        let cmd = receiver.recv().expect("Input thread stopped");
 
         let mut tokens = cmd.split_whitespace();
 
@@ -114,6 +159,16 @@ fn main() {
                 println!("id author Mcthouacbb");
                 println!("option name Threads type spin default 1 min 1 max 1");
                 println!("option name Hash type spin default 24 min 1 max 1048576");
+                println!("option name Ponder type check default false");
+                println!("option name LargePages type check default true");
+                println!("option name QSearch type check default false");
+                println!("option name RootFpuMode type combo default Infinite var Infinite var Reduction var Absolute");
+                println!("option name FpuMode type combo default Reduction var Infinite var Reduction var Absolute");
+                println!("option name MoveSelection type combo default Q var Visits var Q var LCB");
+                println!("option name MoveVisitFloor type spin default 0 min 0 max 100");
+                println!("option name UCI_ShowWDL type check default false");
+                println!("option name Contempt type spin default 0 min -1000 max 1000");
+                searcher.params().print_uci_options();
                 println!("uciok");
             }
             Some("ucinewgame") => {
@@ -131,14 +186,43 @@ fn main() {
             }
             Some("d") => {
                 println!("{}", pos.board());
+                let mut moves = MoveList::new();
+                movegen(pos.board(), &mut moves);
+                // legal moves in san unless lan or uci is asked for
+                let format = match tokens.next() {
+                    Some("lan") => notation::move_to_lan,
+                    Some("uci") => notation::move_to_uci,
+                    _ => notation::move_to_san,
+                };
+                let moves = moves
+                    .iter()
+                    .map(|mv| format(pos.board(), *mv))
+                    .collect::<Vec<String>>();
+                println!("legal moves: {}", moves.join(" "));
             }
             Some("go") => {
                 let mut limits = SearchLimits::new();
                 loop {
                     match tokens.next() {
-                        Some("infinite") => {
-                            limits = SearchLimits::new();
-                            break;
+                        Some("infinite") => limits.infinite = true,
+                        Some("searchmoves") => {
+                            // moves run until the next token that isn't one
+                            while let Some(mv) = tokens
+                                .clone()
+                                .next()
+                                .and_then(|mv_str| notation::parse_move(pos.board(), mv_str))
+                            {
+                                limits.search_moves.push(mv);
+                                tokens.next();
+                            }
+                        }
+                        Some("ponder") => limits.ponder = true,
+                        Some("mate") => {
+                            if let Some(mate_str) = tokens.next() {
+                                if let Ok(mate) = mate_str.parse::<i32>() {
+                                    limits.mate = mate;
+                                }
+                            }
                         }
                         Some("nodes") => {
                             if let Some(nodes_str) = tokens.next() {
@@ -147,6 +231,13 @@ fn main() {
                                 }
                             }
                         }
+                        Some("visits") => {
+                            if let Some(visits_str) = tokens.next() {
+                                if let Ok(visits) = visits_str.parse::<i32>() {
+                                    limits.max_visits = visits;
+                                }
+                            }
+                        }
                         Some("movetime") => {
                             if let Some(time_str) = tokens.next() {
                                 if let Ok(time) = time_str.parse::<i32>() {
@@ -206,11 +297,52 @@ fn main() {
                         }
                     }
                 }
-                let results: search::SearchResults = searcher.run(limits, true, &pos);
-                println!("bestmove {}", results.best_move);
+                if limits.infinite {
+                    // only a stop ends an infinite search, the other limits are dropped
+                    limits = SearchLimits {
+                        search_moves: limits.search_moves,
+                        mate: limits.mate,
+                        ponder: limits.ponder,
+                        infinite: true,
+                        ..SearchLimits::new()
+                    };
+                }
+                let results: search::SearchResults = searcher.run(&limits, true, &pos);
+                let best_move = notation::move_to_uci(pos.board(), results.best_move);
+                if results.ponder_move == Move::NULL {
+                    println!("bestmove {}", best_move);
+                } else {
+                    let mut board = pos.board().clone();
+                    board.make_move(results.best_move);
+                    println!(
+                        "bestmove {} ponder {}",
+                        best_move,
+                        notation::move_to_uci(&board, results.ponder_move)
+                    );
+                }
+                searcher.signals().searching.store(false, Ordering::Relaxed);
             }
             Some("tree") => {
-                searcher.display_tree(1);
+                tree_view::run_tree_command(&searcher, &mut tokens);
+            }
+            Some("savetree") => {
+                let Some(filename) = tokens.next() else {
+                    println!("info string missing filename");
+                    continue;
+                };
+                if let Err(err) = searcher.save_tree(filename) {
+                    println!("info string cannot save tree: {}", err);
+                }
+            }
+            Some("loadtree") => {
+                let Some(filename) = tokens.next() else {
+                    println!("info string missing filename");
+                    continue;
+                };
+                match searcher.load_tree(filename) {
+                    Ok(root_position) => pos = root_position,
+                    Err(err) => println!("info string cannot load tree: {}", err),
+                }
             }
             Some("setoption") => {
                 if tokens.next() != Some("name") {
@@ -223,12 +355,52 @@ fn main() {
                 let value = tokens.next().expect("no value in setoption");
 
                 match name.to_lowercase().as_str() {
+                    // the gui decides when to ponder, nothing to set up for it
+                    "ponder" => {}
                     "hash" => {
-                        searcher.set_hash(value.parse::<u64>().expect("Cannot parse hash into u64"))
+                        searcher
+                            .set_hash(value.parse::<u64>().expect("Cannot parse hash into u64"));
+                        println!("info string {}", searcher.hash_info());
+                    }
+                    "largepages" => {
+                        searcher.set_large_pages(value == "true");
+                        println!("info string {}", searcher.hash_info());
+                    }
+                    "qsearch" => searcher.set_qsearch(value == "true"),
+                    "rootfpumode" => match FpuMode::from_name(value) {
+                        Some(fpu_mode) => searcher.set_fpu_mode(true, fpu_mode),
+                        None => println!("info string invalid value {}", value),
+                    },
+                    "fpumode" => match FpuMode::from_name(value) {
+                        Some(fpu_mode) => searcher.set_fpu_mode(false, fpu_mode),
+                        None => println!("info string invalid value {}", value),
+                    },
+                    "moveselection" => match MoveSelection::from_name(value) {
+                        Some(selection) => searcher.set_move_selection(selection),
+                        None => println!("info string invalid value {}", value),
+                    },
+                    "movevisitfloor" => match value.parse::<u32>() {
+                        Ok(floor) => searcher.set_move_visit_floor(floor.min(100)),
+                        Err(_) => println!("info string invalid value {}", value),
+                    },
+                    "uci_showwdl" => searcher.set_show_wdl(value == "true"),
+                    "contempt" => match value.parse::<i32>() {
+                        Ok(contempt) => searcher.set_contempt(contempt.clamp(-1000, 1000)),
+                        Err(_) => println!("info string invalid value {}", value),
+                    },
+                    _ => {
+                        let Ok(value) = value.parse::<f32>() else {
+                            println!("info string invalid value {}", value);
+                            continue;
+                        };
+                        if !searcher.params_mut().set_by_name(name, value) {
+                            println!("info string unknown option {}", name);
+                        }
                     }
-                    _ => {}
                 }
             }
+            // only meaningful while searching, where the input thread handles them
+            Some("stop") | Some("ponderhit") => {}
             Some("quit") => {
                 return;
             }
@@ -0,0 +1,695 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Write},
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use rand_core::{RngCore, SeedableRng};
+use rand_xorshift::XorShiftRng;
+
+use crate::{
+    chess::{notation, Move},
+    pgn::{self, PgnGame},
+    position::Position,
+    score::MATE_SCORE,
+    search::{FpuMode, MoveSelection, MCTS},
+    selfplay::{
+        init_opening, play_game, Adjudication, GameRecord, GoLimits, Outcome, Player, TimeControl,
+    },
+};
+
+#[derive(Debug, Clone)]
+enum EngineKind {
+    Internal,
+    Uci(String),
+}
+
+#[derive(Debug, Clone)]
+struct EngineConfig {
+    kind: EngineKind,
+    options: Vec<(String, String)>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct SprtConfig {
+    elo0: f64,
+    elo1: f64,
+    alpha: f64,
+    beta: f64,
+}
+
+impl SprtConfig {
+    fn bounds(&self) -> (f64, f64) {
+        (
+            (self.beta / (1.0 - self.alpha)).ln(),
+            ((1.0 - self.beta) / self.alpha).ln(),
+        )
+    }
+}
+
+struct MatchConfig {
+    engines: [EngineConfig; 2],
+    openings: Option<String>,
+    pgn: Option<String>,
+    tc: TimeControl,
+    games: u32,
+    concurrency: u32,
+    adjudication: Adjudication,
+    sprt: Option<SprtConfig>,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Stats {
+    wins: u32,
+    draws: u32,
+    losses: u32,
+}
+
+fn elo_to_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+fn score_to_elo(score: f64) -> f64 {
+    let score = score.clamp(1e-6, 1.0 - 1e-6);
+    -400.0 * (1.0 / score - 1.0).log10()
+}
+
+impl Stats {
+    fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    fn score(&self) -> f64 {
+        (self.wins as f64 + 0.5 * self.draws as f64) / self.games() as f64
+    }
+
+    // per game variance of the score
+    fn variance(&self) -> f64 {
+        let n = self.games() as f64;
+        let score = self.score();
+        let w = self.wins as f64 / n;
+        let d = self.draws as f64 / n;
+        let l = self.losses as f64 / n;
+        w * (1.0 - score).powi(2) + d * (0.5 - score).powi(2) + l * score.powi(2)
+    }
+
+    // elo difference and the half width of its 95% confidence interval
+    fn elo(&self) -> (f64, f64) {
+        let score = self.score();
+        let margin = 1.959964 * (self.variance() / self.games() as f64).sqrt();
+        let lower = score_to_elo(score - margin);
+        let upper = score_to_elo(score + margin);
+        (score_to_elo(score), (upper - lower) / 2.0)
+    }
+
+    // normal approximation to the generalized sprt log likelihood ratio
+    fn llr(&self, sprt: &SprtConfig) -> f64 {
+        let variance = self.variance();
+        if self.games() == 0 || variance == 0.0 {
+            return 0.0;
+        }
+        let s0 = elo_to_score(sprt.elo0);
+        let s1 = elo_to_score(sprt.elo1);
+        (s1 - s0) * (2.0 * self.score() - s0 - s1) * self.games() as f64 / (2.0 * variance)
+    }
+}
+
+struct UciPlayer {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl UciPlayer {
+    fn new(path: &str, options: &[(String, String)]) -> Self {
+        let mut child = Command::new(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("Unable to start engine");
+        let stdin = child.stdin.take().unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+
+        let mut result = Self {
+            child,
+            stdin,
+            stdout,
+        };
+        result.send("uci");
+        result.wait_for("uciok");
+        for (name, value) in options {
+            result.send(format!("setoption name {} value {}", name, value).as_str());
+        }
+        result.sync();
+        result
+    }
+
+    fn send(&mut self, cmd: &str) {
+        writeln!(self.stdin, "{}", cmd).expect("Unable to write to engine");
+        self.stdin.flush().expect("Unable to write to engine");
+    }
+
+    fn read_line(&mut self) -> String {
+        let mut line = String::new();
+        let bytes = self
+            .stdout
+            .read_line(&mut line)
+            .expect("Unable to read from engine");
+        assert!(bytes > 0, "Engine exited unexpectedly");
+        line
+    }
+
+    fn wait_for(&mut self, token: &str) {
+        while self.read_line().trim() != token {}
+    }
+
+    fn sync(&mut self) {
+        self.send("isready");
+        self.wait_for("readyok");
+    }
+}
+
+impl Drop for UciPlayer {
+    fn drop(&mut self) {
+        let _ = writeln!(self.stdin, "quit");
+        let _ = self.stdin.flush();
+        let _ = self.child.wait();
+    }
+}
+
+fn parse_info_score(line: &str) -> Option<i32> {
+    let mut tokens = line.split_whitespace();
+    tokens.find(|token| *token == "score")?;
+    match (tokens.next(), tokens.next().map(str::parse::<i32>)) {
+        (Some("cp"), Some(Ok(cp))) => Some(cp),
+        (Some("mate"), Some(Ok(moves))) if moves > 0 => Some(MATE_SCORE - (2 * moves - 1)),
+        (Some("mate"), Some(Ok(moves))) => Some(-MATE_SCORE - 2 * moves),
+        _ => None,
+    }
+}
+
+impl Player for UciPlayer {
+    fn new_game(&mut self) {
+        self.send("ucinewgame");
+        self.sync();
+    }
+
+    fn go(
+        &mut self,
+        opening: &Position,
+        moves: &[Move],
+        pos: &Position,
+        limits: GoLimits,
+    ) -> (Move, Option<i32>) {
+        let mut cmd = format!("position fen {}", opening.board().to_fen());
+        if !moves.is_empty() {
+            cmd += " moves";
+            let mut board = opening.board().clone();
+            for mv in moves {
+                cmd += format!(" {}", notation::move_to_uci(&board, *mv)).as_str();
+                board.make_move(*mv);
+            }
+        }
+        self.send(cmd.as_str());
+
+        if limits.nodes > 0 {
+            self.send(format!("go nodes {}", limits.nodes).as_str());
+        } else if limits.visits > 0 {
+            self.send(format!("go visits {}", limits.visits).as_str());
+        } else {
+            self.send(
+                format!(
+                    "go wtime {} btime {} winc {} binc {}",
+                    limits.time[0], limits.time[1], limits.inc[0], limits.inc[1]
+                )
+                .as_str(),
+            );
+        }
+
+        let mut score = None;
+        loop {
+            let line = self.read_line();
+            if line.starts_with("info") {
+                if let Some(cp) = parse_info_score(line.as_str()) {
+                    score = Some(cp);
+                }
+            } else if line.starts_with("bestmove") {
+                let mv = line
+                    .split_whitespace()
+                    .nth(1)
+                    .and_then(|mv_str| notation::move_from_uci(pos.board(), mv_str))
+                    .unwrap_or(Move::NULL);
+                return (mv, score);
+            }
+        }
+    }
+}
+
+fn create_player(config: &EngineConfig) -> Box<dyn Player> {
+    match &config.kind {
+        EngineKind::Internal => {
+            let mut searcher = MCTS::new();
+            for (name, value) in &config.options {
+                if name.eq_ignore_ascii_case("hash") {
+                    searcher.set_hash(value.parse().expect("Cannot parse hash into u64"));
+                    continue;
+                }
+                let root_fpu = name.eq_ignore_ascii_case("rootfpumode");
+                if root_fpu || name.eq_ignore_ascii_case("fpumode") {
+                    let fpu_mode = FpuMode::from_name(value).expect("Cannot parse fpu mode");
+                    searcher.set_fpu_mode(root_fpu, fpu_mode);
+                    continue;
+                }
+                if name.eq_ignore_ascii_case("moveselection") {
+                    let selection =
+                        MoveSelection::from_name(value).expect("Cannot parse move selection");
+                    searcher.set_move_selection(selection);
+                    continue;
+                }
+                if name.eq_ignore_ascii_case("movevisitfloor") {
+                    searcher.set_move_visit_floor(
+                        value.parse().expect("Cannot parse move visit floor"),
+                    );
+                    continue;
+                }
+                if name.eq_ignore_ascii_case("qsearch") {
+                    searcher.set_qsearch(value == "true");
+                    continue;
+                }
+                if name.eq_ignore_ascii_case("contempt") {
+                    searcher.set_contempt(value.parse().expect("Cannot parse contempt"));
+                    continue;
+                }
+                let value = value.parse().expect("Cannot parse option value");
+                assert!(
+                    searcher.params_mut().set_by_name(name, value),
+                    "Unknown option {}",
+                    name
+                );
+            }
+            Box::new(searcher)
+        }
+        EngineKind::Uci(path) => Box::new(UciPlayer::new(path, &config.options)),
+    }
+}
+
+fn engine_name(config: &EngineConfig) -> String {
+    let mut name = match &config.kind {
+        EngineKind::Internal => "internal".to_owned(),
+        EngineKind::Uci(path) => path.clone(),
+    };
+    for (option, value) in &config.options {
+        name += format!(" {}={}", option, value).as_str();
+    }
+    name
+}
+
+// epd lines only have the first four fen fields, full fens are accepted too
+fn load_openings(filename: &str) -> Vec<Position> {
+    let file = File::open(filename).expect("Unable to open openings file");
+    let mut openings = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line.expect("Cannot read file");
+        let fields = line.split_whitespace().collect::<Vec<&str>>();
+        if fields.len() < 4 {
+            continue;
+        }
+        let mut fen = fields[0..4].join(" ");
+        if fields.len() >= 6 && fields[4].parse::<u32>().is_ok() && fields[5].parse::<u32>().is_ok()
+        {
+            fen += format!(" {} {}", fields[4], fields[5]).as_str();
+        } else {
+            fen += " 0 1";
+        }
+
+        let mut pos = Position::new();
+        if pos.parse_fen(fen.as_str()) {
+            openings.push(pos);
+        } else {
+            println!("Skipping invalid opening {}", line);
+        }
+    }
+    assert!(!openings.is_empty(), "No openings in {}", filename);
+    openings
+}
+
+pub fn parse_time_control(tc: &str) -> TimeControl {
+    let (base, inc) = tc.split_once('+').unwrap_or((tc, "0"));
+    let base = base.parse::<f64>().expect("Cannot parse tc");
+    let inc = inc.parse::<f64>().expect("Cannot parse tc");
+    TimeControl::Clock((base * 1000.0) as i32, (inc * 1000.0) as i32)
+}
+
+fn parse_engine(value: &str) -> EngineKind {
+    if value == "internal" {
+        EngineKind::Internal
+    } else {
+        EngineKind::Uci(value.to_owned())
+    }
+}
+
+fn parse_option(value: &str) -> (String, String) {
+    let (name, value) = value
+        .split_once('=')
+        .expect("Engine options must be given as Name=Value");
+    (name.to_owned(), value.to_owned())
+}
+
+fn parse_config(args: &[String]) -> MatchConfig {
+    let mut config = MatchConfig {
+        engines: [
+            EngineConfig {
+                kind: EngineKind::Internal,
+                options: Vec::new(),
+            },
+            EngineConfig {
+                kind: EngineKind::Internal,
+                options: Vec::new(),
+            },
+        ],
+        openings: None,
+        pgn: None,
+        tc: TimeControl::Visits(5000),
+        games: 1000,
+        concurrency: 1,
+        adjudication: Adjudication {
+            draw_move_number: 40,
+            draw_move_count: 8,
+            draw_score: 10,
+            win_move_count: 3,
+            win_score: 1000,
+        },
+        sprt: None,
+    };
+    let mut sprt = SprtConfig {
+        elo0: 0.0,
+        elo1: 5.0,
+        alpha: 0.05,
+        beta: 0.05,
+    };
+    let mut use_sprt = false;
+
+    let mut tokens = args.iter();
+    while let Some(key) = tokens.next() {
+        let Some(value) = tokens.next() else {
+            println!("missing value for {}", key);
+            break;
+        };
+        match key.as_str() {
+            "engine1" => config.engines[0].kind = parse_engine(value),
+            "engine2" => config.engines[1].kind = parse_engine(value),
+            "option1" => config.engines[0].options.push(parse_option(value)),
+            "option2" => config.engines[1].options.push(parse_option(value)),
+            "openings" => config.openings = Some(value.clone()),
+            "pgn" => config.pgn = Some(value.clone()),
+            "nodes" => config.tc = TimeControl::Nodes(value.parse().expect("Cannot parse nodes")),
+            "visits" => {
+                config.tc = TimeControl::Visits(value.parse().expect("Cannot parse visits"))
+            }
+            "tc" => config.tc = parse_time_control(value),
+            "games" => config.games = value.parse().expect("Cannot parse games"),
+            "concurrency" => config.concurrency = value.parse().expect("Cannot parse concurrency"),
+            "drawmovenumber" => {
+                config.adjudication.draw_move_number =
+                    value.parse().expect("Cannot parse drawmovenumber")
+            }
+            "drawmovecount" => {
+                config.adjudication.draw_move_count =
+                    value.parse().expect("Cannot parse drawmovecount")
+            }
+            "drawscore" => {
+                config.adjudication.draw_score = value.parse().expect("Cannot parse drawscore")
+            }
+            "resignmovecount" => {
+                config.adjudication.win_move_count =
+                    value.parse().expect("Cannot parse resignmovecount")
+            }
+            "resignscore" => {
+                config.adjudication.win_score = value.parse().expect("Cannot parse resignscore")
+            }
+            "elo0" => {
+                sprt.elo0 = value.parse().expect("Cannot parse elo0");
+                use_sprt = true;
+            }
+            "elo1" => {
+                sprt.elo1 = value.parse().expect("Cannot parse elo1");
+                use_sprt = true;
+            }
+            "alpha" => {
+                sprt.alpha = value.parse().expect("Cannot parse alpha");
+                use_sprt = true;
+            }
+            "beta" => {
+                sprt.beta = value.parse().expect("Cannot parse beta");
+                use_sprt = true;
+            }
+            _ => println!("unknown match option {}", key),
+        }
+    }
+
+    if use_sprt {
+        config.sprt = Some(sprt);
+    }
+    config
+}
+
+struct MatchState {
+    next_pair: u32,
+    stats: Stats,
+    finished: bool,
+    pgn_file: Option<File>,
+}
+
+fn termination(reason: &str) -> &'static str {
+    match reason {
+        "loses on time" => "time forfeit",
+        "illegal move" => "rules infraction",
+        "draw by adjudication" | "win by adjudication" => "adjudication",
+        _ => "normal",
+    }
+}
+
+fn game_pgn(
+    config: &MatchConfig,
+    record: &GameRecord,
+    opening: &Position,
+    engine1_color: usize,
+    round: u32,
+) -> String {
+    let mut pgn = PgnGame::new(opening.board());
+    pgn.add_tag("Event", "Aquarii match");
+    pgn.add_tag("Round", round.to_string().as_str());
+    pgn.add_tag(
+        "White",
+        engine_name(&config.engines[engine1_color]).as_str(),
+    );
+    pgn.add_tag(
+        "Black",
+        engine_name(&config.engines[1 - engine1_color]).as_str(),
+    );
+    match config.tc {
+        TimeControl::Nodes(nodes) => pgn.add_tag("Nodes", nodes.to_string().as_str()),
+        TimeControl::Visits(visits) => pgn.add_tag("Visits", visits.to_string().as_str()),
+        TimeControl::Clock(time, inc) => pgn.add_tag(
+            "TimeControl",
+            format!("{}+{}", time as f32 / 1000.0, inc as f32 / 1000.0).as_str(),
+        ),
+    }
+    pgn.add_tag("Termination", termination(record.reason));
+    for (mv, score) in record.moves.iter().zip(&record.scores) {
+        pgn.add_move(*mv, score.map(pgn::eval_comment));
+    }
+    pgn.set_result(record.outcome);
+    pgn.to_pgn()
+}
+
+fn print_score(stats: &Stats) {
+    println!(
+        "Score of engine1 vs engine2: {} - {} - {} [{:.3}] {}",
+        stats.wins,
+        stats.losses,
+        stats.draws,
+        stats.score(),
+        stats.games()
+    );
+}
+
+fn print_stats(stats: &Stats, sprt: &Option<SprtConfig>) {
+    if stats.games() == 0 {
+        println!("No games played");
+        return;
+    }
+    print_score(stats);
+    let (elo, error) = stats.elo();
+    println!("Elo difference: {:.1} +/- {:.1}", elo, error);
+    if let Some(sprt) = sprt {
+        let (lower, upper) = sprt.bounds();
+        println!(
+            "SPRT [{}, {}]: LLR {:.2} ({:.2}, {:.2})",
+            sprt.elo0,
+            sprt.elo1,
+            stats.llr(sprt),
+            lower,
+            upper
+        );
+    }
+}
+
+fn match_thread(
+    state: Arc<Mutex<MatchState>>,
+    config: Arc<MatchConfig>,
+    openings: Arc<Vec<Position>>,
+    thread_id: u32,
+) {
+    let seed = rand::rng().next_u64();
+    println!("Thread {} RNG seed: {}", thread_id, seed);
+    let mut rng = XorShiftRng::seed_from_u64(seed);
+
+    let mut players = [
+        create_player(&config.engines[0]),
+        create_player(&config.engines[1]),
+    ];
+
+    loop {
+        let pair = {
+            let mut state = state.lock().unwrap();
+            if state.finished || state.next_pair * 2 >= config.games {
+                return;
+            }
+            state.next_pair += 1;
+            state.next_pair - 1
+        };
+
+        let opening = if openings.is_empty() {
+            init_opening(&mut rng)
+        } else {
+            openings[pair as usize % openings.len()].clone()
+        };
+
+        // each opening is played twice with colors reversed
+        for engine1_color in 0..2 {
+            let [engine1, engine2] = &mut players;
+            let (white, black) = if engine1_color == 0 {
+                (engine1, engine2)
+            } else {
+                (engine2, engine1)
+            };
+            let record = play_game(
+                white.as_mut(),
+                black.as_mut(),
+                &opening,
+                config.tc,
+                &config.adjudication,
+            );
+
+            let mut state = state.lock().unwrap();
+            match (record.outcome, engine1_color) {
+                (Outcome::Draw, _) => state.stats.draws += 1,
+                (Outcome::WhiteWin, 0) | (Outcome::BlackWin, 1) => state.stats.wins += 1,
+                _ => state.stats.losses += 1,
+            }
+
+            let stats = state.stats;
+            if let Some(pgn_file) = &mut state.pgn_file {
+                let pgn = game_pgn(&config, &record, &opening, engine1_color, stats.games());
+                pgn_file
+                    .write_all(pgn.as_bytes())
+                    .expect("Unable to write pgn");
+            }
+            println!(
+                "Finished game {} ({} vs {}): {} {{{}}}",
+                stats.games(),
+                if engine1_color == 0 {
+                    "engine1"
+                } else {
+                    "engine2"
+                },
+                if engine1_color == 0 {
+                    "engine2"
+                } else {
+                    "engine1"
+                },
+                pgn::result_str(Some(record.outcome)),
+                record.reason
+            );
+            if stats.games().is_multiple_of(10) {
+                print_stats(&stats, &config.sprt);
+            } else {
+                print_score(&stats);
+            }
+
+            if let Some(sprt) = &config.sprt {
+                let (lower, upper) = sprt.bounds();
+                let llr = stats.llr(sprt);
+                if !state.finished && (llr <= lower || llr >= upper) {
+                    println!(
+                        "SPRT {} after {} games",
+                        if llr >= upper {
+                            "accepted H1"
+                        } else {
+                            "accepted H0"
+                        },
+                        stats.games()
+                    );
+                    state.finished = true;
+                }
+            }
+        }
+    }
+}
+
+pub fn run_match(args: &[String]) {
+    let config = Arc::new(parse_config(args));
+    let openings = Arc::new(match &config.openings {
+        Some(filename) => load_openings(filename),
+        None => Vec::new(),
+    });
+
+    println!("engine1: {}", engine_name(&config.engines[0]));
+    println!("engine2: {}", engine_name(&config.engines[1]));
+    match config.tc {
+        TimeControl::Nodes(nodes) => println!("{} nodes per move", nodes),
+        TimeControl::Visits(visits) => println!("{} visits per move", visits),
+        TimeControl::Clock(time, inc) => println!("tc {}ms+{}ms", time, inc),
+    }
+    if openings.is_empty() {
+        println!("Using random 8 ply openings");
+    } else {
+        println!("Loaded {} openings", openings.len());
+    }
+    println!(
+        "Playing up to {} games on {} threads",
+        config.games, config.concurrency
+    );
+
+    let state = Arc::new(Mutex::new(MatchState {
+        next_pair: 0,
+        stats: Stats::default(),
+        finished: false,
+        pgn_file: config
+            .pgn
+            .as_ref()
+            .map(|filename| File::create(filename).expect("Unable to create pgn file")),
+    }));
+
+    let mut handles = Vec::new();
+    for i in 0..config.concurrency {
+        let state = state.clone();
+        let config = config.clone();
+        let openings = openings.clone();
+        handles.push(thread::spawn(move || {
+            match_thread(state, config, openings, i);
+        }));
+    }
+
+    for handle in handles {
+        let _ = handle.join();
+    }
+
+    let state = state.lock().unwrap();
+    println!("Final results:");
+    print_stats(&state.stats, &config.sprt);
+}
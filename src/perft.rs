@@ -1,10 +1,10 @@
 use crate::chess::{
-    movegen::{movegen, MoveList},
-    Board,
+    movegen::{movegen, movegen_noisy, movegen_quiet, MoveList},
+    Board, MoveKind,
 };
 use std::time::Instant;
 
-fn perft<const ROOT: bool>(board: &Board, depth: i32) -> u64 {
+fn perft<const ROOT: bool>(board: &mut Board, depth: i32) -> u64 {
     if depth == 0 {
         return 1;
     }
@@ -18,9 +18,9 @@ fn perft<const ROOT: bool>(board: &Board, depth: i32) -> u64 {
     }
 
     for mv in moves {
-        let mut new_board = board.clone();
-        new_board.make_move(mv);
-        let sub_nodes = perft::<false>(&new_board, depth - 1);
+        let undo = board.make_move(mv);
+        let sub_nodes = perft::<false>(board, depth - 1);
+        board.unmake_move(mv, &undo);
         if ROOT {
             println!("{}: {}", mv, sub_nodes);
         }
@@ -34,7 +34,7 @@ fn perft<const ROOT: bool>(board: &Board, depth: i32) -> u64 {
     nodes
 }
 
-fn test_zobrist_key(board: &Board, depth: i32) {
+fn test_zobrist_key(board: &mut Board, depth: i32) {
     assert!(board.zkey() == board.recompute_zkey());
 
     if depth == 0 {
@@ -45,12 +45,64 @@ fn test_zobrist_key(board: &Board, depth: i32) {
     movegen(board, &mut moves);
 
     for mv in moves {
-        let mut new_board = board.clone();
-        new_board.make_move(mv);
-        test_zobrist_key(&new_board, depth - 1);
+        let undo = board.make_move(mv);
+        test_zobrist_key(board, depth - 1);
+        board.unmake_move(mv, &undo);
     }
 }
 
+// noisy and quiet generation split the full move list, with every move in exactly one of them
+fn test_movegen_split(board: &mut Board, depth: i32) -> bool {
+    let mut moves = MoveList::new();
+    let mut noisy = MoveList::new();
+    let mut quiet = MoveList::new();
+    movegen(board, &mut moves);
+    movegen_noisy(board, &mut noisy);
+    movegen_quiet(board, &mut quiet);
+
+    if noisy.len() + quiet.len() != moves.len() {
+        return false;
+    }
+    for mv in &moves {
+        let is_noisy = match mv.kind() {
+            MoveKind::Enpassant | MoveKind::Promotion => true,
+            MoveKind::Castle => false,
+            MoveKind::None => board.piece_at(mv.to_sq()).is_some(),
+        };
+        let expected = if is_noisy { &noisy } else { &quiet };
+        if !expected.contains(mv) {
+            return false;
+        }
+    }
+
+    if depth <= 1 {
+        return true;
+    }
+    moves.iter().all(|mv| {
+        let undo = board.make_move(*mv);
+        let passed = test_movegen_split(board, depth - 1);
+        board.unmake_move(*mv, &undo);
+        passed
+    })
+}
+
+// unmaking every move gives back exactly the board it was made on
+fn test_unmake(board: &mut Board, depth: i32) -> bool {
+    if depth == 0 {
+        return true;
+    }
+
+    let mut moves = MoveList::new();
+    movegen(board, &mut moves);
+    moves.iter().all(|mv| {
+        let before = board.clone();
+        let undo = board.make_move(*mv);
+        let passed = test_unmake(board, depth - 1);
+        board.unmake_move(*mv, &undo);
+        passed && *board == before
+    })
+}
+
 struct PerftTest {
     fen: &'static str,
     depths: [u64; 6],
@@ -193,15 +245,15 @@ pub fn run_perft_tests() {
     let start = Instant::now();
     let mut total_nodes = 0u64;
 
-    for test in perft_tests {
-        let board = Board::from_fen(test.fen).unwrap();
+    for test in &perft_tests {
+        let mut board = Board::from_fen(test.fen).unwrap();
         println!("fen: {}", test.fen);
         for d in 1..=6 {
             // skip the ones that take really long
             if test.depths[(d - 1) as usize] > 100_000_000 {
                 continue;
             }
-            let nodes = perft::<false>(&board, d);
+            let nodes = perft::<false>(&mut board, d);
             total_nodes += nodes;
             if test.depths[(d - 1) as usize] == nodes {
                 passed += 1;
@@ -225,4 +277,36 @@ pub fn run_perft_tests() {
         total_nodes as f64 / start.elapsed().as_secs_f64()
     );
     println!("passed {} out of {}", passed, failed + passed);
+
+    let split_passed = perft_tests
+        .iter()
+        .filter(|test| {
+            let passed = test_movegen_split(&mut Board::from_fen(test.fen).unwrap(), 3);
+            if !passed {
+                println!("noisy/quiet split failed for {}", test.fen);
+            }
+            passed
+        })
+        .count();
+    println!(
+        "noisy/quiet split passed {} out of {}",
+        split_passed,
+        perft_tests.len()
+    );
+
+    let unmake_passed = perft_tests
+        .iter()
+        .filter(|test| {
+            let passed = test_unmake(&mut Board::from_fen(test.fen).unwrap(), 3);
+            if !passed {
+                println!("unmake failed for {}", test.fen);
+            }
+            passed
+        })
+        .count();
+    println!(
+        "unmake passed {} out of {}",
+        unmake_passed,
+        perft_tests.len()
+    );
 }
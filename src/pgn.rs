@@ -0,0 +1,315 @@
+use std::io::BufRead;
+
+use crate::{
+    chess::{notation, Board, Move},
+    position::Position,
+    score::MATE_SCORE,
+    selfplay::Outcome,
+    types::Color,
+};
+
+// the seven tag roster, always written first and in this order
+const ROSTER: [&str; 7] = ["Event", "Site", "Date", "Round", "White", "Black", "Result"];
+
+pub struct PgnGame {
+    start: Board,
+    tags: Vec<(String, String)>,
+    moves: Vec<Move>,
+    comments: Vec<Option<String>>,
+    result: Option<Outcome>,
+}
+
+pub fn result_str(result: Option<Outcome>) -> &'static str {
+    match result {
+        Some(Outcome::WhiteWin) => "1-0",
+        Some(Outcome::Draw) => "1/2-1/2",
+        Some(Outcome::BlackWin) => "0-1",
+        None => "*",
+    }
+}
+
+// centipawn score from the mover's perspective in the usual gui format, +0.35 or -M3
+pub fn eval_comment(cp: i32) -> String {
+    if cp.abs() >= MATE_SCORE - 1000 {
+        let plies = MATE_SCORE - cp.abs();
+        let sign = if cp > 0 { '+' } else { '-' };
+        format!("{}M{}", sign, (plies + 1) / 2)
+    } else {
+        format!("{:+.2}", cp as f32 / 100.0)
+    }
+}
+
+impl PgnGame {
+    pub fn new(start: &Board) -> Self {
+        Self {
+            start: start.clone(),
+            tags: Vec::new(),
+            moves: Vec::new(),
+            comments: Vec::new(),
+            result: None,
+        }
+    }
+
+    pub fn add_tag(&mut self, name: &str, value: &str) {
+        self.tags.push((name.to_owned(), value.to_owned()));
+    }
+
+    pub fn add_move(&mut self, mv: Move, comment: Option<String>) {
+        self.moves.push(mv);
+        self.comments.push(comment);
+    }
+
+    pub fn set_result(&mut self, result: Outcome) {
+        self.result = Some(result);
+    }
+
+    fn tag_value(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn to_pgn(&self) -> String {
+        let mut pgn = String::new();
+        for name in ROSTER {
+            let value = if name == "Result" {
+                result_str(self.result)
+            } else {
+                self.tag_value(name).unwrap_or("?")
+            };
+            pgn += format!("[{} \"{}\"]\n", name, value).as_str();
+        }
+
+        let start_fen = self.start.to_fen();
+        if start_fen != Board::startpos().to_fen() {
+            pgn += "[SetUp \"1\"]\n";
+            pgn += format!("[FEN \"{}\"]\n", start_fen).as_str();
+        }
+
+        for (name, value) in &self.tags {
+            if !ROSTER.contains(&name.as_str()) {
+                pgn += format!("[{} \"{}\"]\n", name, value).as_str();
+            }
+        }
+        pgn += "\n";
+
+        let mut tokens = Vec::new();
+        let mut board = self.start.clone();
+        let mut move_number = 1;
+        for (i, (mv, comment)) in self.moves.iter().zip(&self.comments).enumerate() {
+            if board.stm() == Color::White {
+                tokens.push(format!("{}.", move_number));
+            } else if i == 0 {
+                tokens.push(format!("{}...", move_number));
+            }
+            tokens.push(notation::move_to_san(&board, *mv));
+            if let Some(comment) = comment {
+                tokens.push(format!("{{{}}}", comment));
+            }
+
+            if board.stm() == Color::Black {
+                move_number += 1;
+            }
+            board.make_move(*mv);
+        }
+        tokens.push(result_str(self.result).to_owned());
+
+        // keep lines under 80 characters
+        let mut line_len = 0;
+        for token in tokens {
+            if line_len > 0 && line_len + token.len() + 1 > 80 {
+                pgn += "\n";
+                line_len = 0;
+            } else if line_len > 0 {
+                pgn += " ";
+                line_len += 1;
+            }
+            line_len += token.len();
+            pgn += token.as_str();
+        }
+        pgn += "\n\n";
+        pgn
+    }
+}
+
+pub struct ParsedGame {
+    pub tags: Vec<(String, String)>,
+    pub start: Position,
+    pub moves: Vec<Move>,
+    pub result: Option<Outcome>,
+}
+
+fn parse_result(token: &str) -> Option<Option<Outcome>> {
+    match token {
+        "1-0" => Some(Some(Outcome::WhiteWin)),
+        "1/2-1/2" => Some(Some(Outcome::Draw)),
+        "0-1" => Some(Some(Outcome::BlackWin)),
+        "*" => Some(None),
+        _ => None,
+    }
+}
+
+fn parse_tag(line: &str) -> Option<(String, String)> {
+    let inner = line.trim().strip_prefix('[')?.strip_suffix(']')?;
+    let (name, value) = inner.split_once(' ')?;
+    let value = value.trim().strip_prefix('"')?.strip_suffix('"')?;
+    Some((name.to_owned(), value.replace("\\\"", "\"")))
+}
+
+// splits movetext into san tokens, dropping comments, variations, nags and move numbers
+fn movetext_tokens(movetext: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut comment = false;
+    let mut line_comment = false;
+    let mut variation_depth = 0;
+    let mut token_start = None;
+
+    for (i, c) in movetext.char_indices() {
+        let in_token = token_start.is_some();
+        let skipping = comment || line_comment || variation_depth > 0;
+        if in_token && (c.is_whitespace() || "{};()".contains(c)) {
+            tokens.push(&movetext[token_start.unwrap()..i]);
+            token_start = None;
+        }
+
+        match c {
+            '}' if comment => comment = false,
+            '\n' if line_comment => line_comment = false,
+            _ if comment || line_comment => {}
+            '{' => comment = true,
+            ';' => line_comment = true,
+            '(' => variation_depth += 1,
+            ')' if variation_depth > 0 => variation_depth -= 1,
+            _ if skipping || c.is_whitespace() => {}
+            _ if token_start.is_none() => token_start = Some(i),
+            _ => {}
+        }
+    }
+    if let Some(start) = token_start {
+        tokens.push(&movetext[start..]);
+    }
+
+    tokens
+        .into_iter()
+        .map(|token| token.rsplit('.').next().unwrap())
+        .filter(|token| !token.is_empty() && !token.starts_with('$'))
+        .collect()
+}
+
+fn parse_game(tags: Vec<(String, String)>, movetext: &str) -> Result<ParsedGame, String> {
+    let mut start = Position::new();
+    if let Some((_, fen)) = tags.iter().find(|(name, _)| name == "FEN") {
+        if !start.parse_fen(fen) {
+            return Err(format!("invalid fen {}", fen));
+        }
+    }
+
+    let mut game = ParsedGame {
+        tags,
+        start: start.clone(),
+        moves: Vec::new(),
+        result: None,
+    };
+
+    let mut pos = start;
+    for token in movetext_tokens(movetext) {
+        if let Some(result) = parse_result(token) {
+            game.result = result;
+            break;
+        }
+        let Some(mv) = notation::move_from_san(pos.board(), token) else {
+            return Err(format!(
+                "illegal move {} in {}",
+                token,
+                pos.board().to_fen()
+            ));
+        };
+        pos.make_move(mv);
+        game.moves.push(mv);
+    }
+
+    // the result tag wins over a missing or mismatching termination marker
+    if let Some((_, result)) = game.tags.iter().find(|(name, _)| name == "Result") {
+        if let Some(result) = parse_result(result) {
+            game.result = result;
+        }
+    }
+
+    Ok(game)
+}
+
+// streams games out of a pgn file one at a time
+pub struct PgnReader<R: BufRead> {
+    reader: R,
+    pending: Option<String>,
+}
+
+impl<R: BufRead> PgnReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            pending: None,
+        }
+    }
+
+    // pgn files aren't always utf-8, so bad bytes are replaced instead of failing the whole file
+    fn read_line(&mut self) -> Option<String> {
+        let mut bytes = Vec::new();
+        let read = self
+            .reader
+            .read_until(b'\n', &mut bytes)
+            .expect("Cannot read file");
+        if read == 0 {
+            return None;
+        }
+        if bytes.ends_with(b"\n") {
+            bytes.pop();
+            if bytes.ends_with(b"\r") {
+                bytes.pop();
+            }
+        }
+        Some(String::from_utf8_lossy(&bytes).into_owned())
+    }
+}
+
+impl<R: BufRead> Iterator for PgnReader<R> {
+    type Item = Result<ParsedGame, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut tags = Vec::new();
+        let mut movetext = String::new();
+        let mut open_comments = 0i32;
+
+        loop {
+            let line = match self.pending.take() {
+                Some(line) => line,
+                None => match self.read_line() {
+                    Some(line) => line,
+                    None => break,
+                },
+            };
+
+            if open_comments == 0 && line.trim_start().starts_with('[') {
+                // a tag after movetext starts the next game
+                if !movetext.trim().is_empty() {
+                    self.pending = Some(line);
+                    break;
+                }
+                if let Some(tag) = parse_tag(line.as_str()) {
+                    tags.push(tag);
+                }
+                continue;
+            }
+
+            open_comments += line.matches('{').count() as i32 - line.matches('}').count() as i32;
+            movetext += line.as_str();
+            movetext += "\n";
+        }
+
+        if tags.is_empty() && movetext.trim().is_empty() {
+            return None;
+        }
+        Some(parse_game(tags, movetext.as_str()))
+    }
+}
@@ -1,38 +1,73 @@
-use crate::chess::{Board, Move, ZobristKey};
+use crate::chess::{Board, Move, Undo, ZobristKey};
 
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone)]
 pub struct Position {
     board: Board,
     keys: Vec<ZobristKey>,
+    // moves that can be taken back with unmake_move
+    undos: Vec<(Move, Undo)>,
 }
 
+// the undo stack is only how the position was reached, not part of it
+impl PartialEq for Position {
+    fn eq(&self, other: &Self) -> bool {
+        self.board == other.board && self.keys == other.keys
+    }
+}
+
+impl Eq for Position {}
+
 impl Position {
     pub fn new() -> Self {
         Self {
             board: Board::startpos(),
             keys: Vec::with_capacity(512),
+            undos: Vec::with_capacity(512),
         }
     }
     pub fn set_startpos(&mut self) {
         self.board = Board::startpos();
         self.keys.clear();
+        self.undos.clear();
     }
     pub fn parse_fen(&mut self, fen: &str) -> bool {
         if let Some(board) = Board::from_fen(fen) {
             self.board = board;
             self.keys.clear();
+            self.undos.clear();
             return true;
         }
         false
     }
 
+    // keys holds the hashes of the earlier positions, used for repetition detection
+    pub fn from_parts(board: Board, keys: Vec<ZobristKey>) -> Self {
+        Self {
+            board,
+            keys,
+            undos: Vec::new(),
+        }
+    }
+
+    pub fn keys(&self) -> &[ZobristKey] {
+        &self.keys
+    }
+
     pub fn board(&self) -> &Board {
         &self.board
     }
 
     pub fn make_move(&mut self, mv: Move) {
         self.keys.push(self.board.zkey());
-        self.board.make_move(mv);
+        let undo = self.board.make_move(mv);
+        self.undos.push((mv, undo));
+    }
+
+    // takes back the last make_move, neither allocates once the stacks have grown
+    pub fn unmake_move(&mut self) {
+        let (mv, undo) = self.undos.pop().expect("No move to unmake");
+        self.board.unmake_move(mv, &undo);
+        self.keys.pop();
     }
 
     pub fn is_drawn(&self, depth: i32) -> bool {
@@ -0,0 +1,67 @@
+use crate::{
+    chess::{
+        movegen::{movegen, movegen_noisy, MoveList},
+        see, Board,
+    },
+    eval,
+    score::MATE_SCORE,
+};
+
+// keeps the worst case cost of resolving a leaf bounded
+const MAX_PLY: i32 = 8;
+
+fn qsearch_impl(board: &mut Board, mut alpha: i32, beta: i32, ply: i32, nodes: &mut u32) -> i32 {
+    *nodes += 1;
+
+    // every evasion when in check, otherwise only noisy moves
+    let in_check = board.checkers().any();
+    let mut moves = MoveList::new();
+    if in_check {
+        movegen(board, &mut moves);
+    } else {
+        movegen_noisy(board, &mut moves);
+    }
+    if in_check && moves.is_empty() {
+        return -MATE_SCORE + ply;
+    }
+
+    let static_eval = eval::eval(board);
+    if ply >= MAX_PLY {
+        return static_eval;
+    }
+
+    // standing pat isn't allowed in check, every evasion is searched instead
+    let mut best = if in_check {
+        -MATE_SCORE + ply
+    } else {
+        static_eval
+    };
+    if best >= beta {
+        return best;
+    }
+    alpha = alpha.max(best);
+
+    for mv in moves {
+        if !in_check && !see::see(board, mv, 0) {
+            continue;
+        }
+        let undo = board.make_move(mv);
+        let score = -qsearch_impl(board, -beta, -alpha, ply + 1, nodes);
+        board.unmake_move(mv, &undo);
+        if score > best {
+            best = score;
+            if score >= beta {
+                break;
+            }
+            alpha = alpha.max(score);
+        }
+    }
+
+    best
+}
+
+// eval in centipawns for the side to move after resolving captures that don't lose material,
+// nodes is incremented for every position visited, board is left as it was
+pub fn qsearch(board: &mut Board, nodes: &mut u32) -> i32 {
+    qsearch_impl(board, -MATE_SCORE, MATE_SCORE, 0, nodes)
+}
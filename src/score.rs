@@ -1,5 +1,7 @@
 use core::fmt;
 
+pub const MATE_SCORE: i32 = 32000;
+
 pub fn sigmoid_inv(x: f32, scale: f32) -> f32 {
     scale * (x / (1.0 - x)).ln()
 }
@@ -8,6 +10,65 @@ pub fn sigmoid(x: f32, scale: f32) -> f32 {
     1.0 / (1.0 + (-x / scale).exp())
 }
 
+// win/draw/loss probabilities from the side to move's perspective
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Wdl {
+    pub win: f32,
+    pub draw: f32,
+    pub loss: f32,
+}
+
+impl Wdl {
+    pub const DRAW: Self = Self {
+        win: 0.0,
+        draw: 1.0,
+        loss: 0.0,
+    };
+    pub const LOSS: Self = Self {
+        win: 0.0,
+        draw: 0.0,
+        loss: 1.0,
+    };
+
+    // the expected score is sigmoid(cp) whatever the draw margin, the margin only decides how
+    // much of it is draws: the part left over when win and loss are shifted by the margin
+    pub fn from_cp(cp: f32, scale: f32, draw_margin: f32) -> Self {
+        let score = sigmoid(cp, scale);
+        let draw = 1.0 - sigmoid(cp - draw_margin, scale) - sigmoid(-cp - draw_margin, scale);
+        let draw = draw.clamp(0.0, 2.0 * score.min(1.0 - score));
+        let win = score - draw / 2.0;
+        Self {
+            win,
+            draw,
+            loss: 1.0 - win - draw,
+        }
+    }
+
+    pub fn flip(&self) -> Self {
+        Self {
+            win: self.loss,
+            draw: self.draw,
+            loss: self.win,
+        }
+    }
+
+    // expected score
+    pub fn score(&self) -> f32 {
+        self.win + self.draw / 2.0
+    }
+
+    // moves the value of draws into the win/loss split so that the expected score of the
+    // result is win + draw * draw_value
+    pub fn with_draw_value(&self, draw_value: f32) -> Self {
+        let win = (self.win + self.draw * (draw_value - 0.5)).clamp(0.0, 1.0 - self.draw);
+        Self {
+            win,
+            draw: self.draw,
+            loss: 1.0 - win - self.draw,
+        }
+    }
+}
+
 #[derive(PartialEq, Eq, Clone, Copy)]
 #[repr(u8)]
 pub enum GameResult {
@@ -16,6 +77,17 @@ pub enum GameResult {
     Drawn,
 }
 
+impl GameResult {
+    pub fn from_raw(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::NonTerminal),
+            1 => Some(Self::Mated),
+            2 => Some(Self::Drawn),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub enum MateScore {
     Loss(u16),
@@ -27,7 +99,7 @@ pub enum Score {
     Win(u16),
     Draw,
     Loss(u16),
-    Normal(f32),
+    Normal(Wdl),
 }
 
 impl Score {
@@ -36,7 +108,43 @@ impl Score {
             Self::Win(dist) => Self::Loss(*dist),
             Self::Draw => Self::Draw,
             Self::Loss(dist) => Self::Win(*dist),
-            Self::Normal(score) => Self::Normal(1.0 - score),
+            Self::Normal(wdl) => Self::Normal(wdl.flip()),
+        }
+    }
+
+    // expected score for the side to move
+    pub fn win_prob(&self) -> f32 {
+        match self {
+            Self::Win(_) => 1.0,
+            Self::Draw => 0.5,
+            Self::Loss(_) => 0.0,
+            Self::Normal(wdl) => wdl.score(),
+        }
+    }
+
+    // win/draw/loss permille for uci_showwdl
+    pub fn wdl(&self) -> (u32, u32, u32) {
+        match self {
+            Self::Win(_) => (1000, 0, 0),
+            Self::Draw => (0, 1000, 0),
+            Self::Loss(_) => (0, 0, 1000),
+            Self::Normal(wdl) => {
+                let win = ((wdl.win * 1000.0).round() as u32).min(1000);
+                let draw = ((wdl.draw * 1000.0).round() as u32).min(1000 - win);
+                (win, draw, 1000 - win - draw)
+            }
+        }
+    }
+
+    // centipawns from the side to move's perspective, mates are MATE_SCORE minus the distance in plies
+    pub fn cp(&self) -> i32 {
+        match self {
+            Self::Win(dist) => MATE_SCORE - *dist as i32,
+            Self::Draw => 0,
+            Self::Loss(dist) => -MATE_SCORE + *dist as i32,
+            Self::Normal(wdl) => sigmoid_inv(wdl.score(), 400.0)
+                .round()
+                .clamp(-20000.0, 20000.0) as i32,
         }
     }
 
@@ -45,7 +153,7 @@ impl Score {
             Self::Win(dist) => format!("mate {}", (*dist + 1) / 2),
             Self::Draw => format!("cp 0"),
             Self::Loss(dist) => format!("mate -{}", *dist / 2),
-            Self::Normal(score) => format!("cp {}", sigmoid_inv(*score, 400.0).round()),
+            Self::Normal(wdl) => format!("cp {}", sigmoid_inv(wdl.score(), 400.0).round()),
         }
     }
 }
@@ -56,7 +164,7 @@ impl fmt::Display for Score {
             Self::Win(dist) => write!(f, "win {} plies", *dist),
             Self::Draw => write!(f, "draw"),
             Self::Loss(dist) => write!(f, "loss {} plies", *dist),
-            Self::Normal(score) => write!(f, "cp {}", sigmoid_inv(*score, 400.0).round()),
+            Self::Normal(wdl) => write!(f, "cp {}", sigmoid_inv(wdl.score(), 400.0).round()),
         }
     }
 }
@@ -1,17 +1,86 @@
-use std::{num::NonZeroI16, time::Instant};
+use std::{
+    fs,
+    num::NonZeroI16,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
 
 use crate::{
     chess::{
         movegen::{movegen, MoveList},
-        Move,
+        notation, Move,
     },
     eval,
     position::Position,
-    score::{sigmoid, GameResult, MateScore, Score},
+    qsearch,
+    score::{sigmoid, GameResult, MateScore, Score, Wdl},
     tree::{Node, NodeIndex, Tree},
 };
 
-#[derive(Copy, Clone)]
+// how many standard errors the lower confidence bound sits below the mean
+const LCB_Z: f32 = 1.96;
+
+// minimum time between info lines when the depth isn't increasing
+const INFO_INTERVAL_MS: u128 = 1000;
+
+// one child in the tree explorer, q and uct are from the perspective of the side choosing it
+pub struct TreeRow {
+    pub parent: Option<usize>,
+    pub depth: u32,
+    pub san: String,
+    pub visits: u32,
+    pub share: f32,
+    pub q: f32,
+    pub policy: f32,
+    pub uct: f32,
+    pub score: Score,
+    pub proven: bool,
+    pub inactive: bool,
+}
+
+// value given to unvisited children, infinite makes every child get visited once first
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum FpuMode {
+    Infinite,
+    Reduction,
+    Absolute,
+}
+
+impl FpuMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "infinite" => Some(Self::Infinite),
+            "reduction" => Some(Self::Reduction),
+            "absolute" => Some(Self::Absolute),
+            _ => None,
+        }
+    }
+}
+
+// how the move to play is picked from the root's children once the search is over
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum MoveSelection {
+    Visits,
+    Q,
+    Lcb,
+}
+
+impl MoveSelection {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "visits" => Some(Self::Visits),
+            "q" => Some(Self::Q),
+            "lcb" => Some(Self::Lcb),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct SearchLimits {
     pub use_clock: bool,
     pub time: i32,
@@ -19,14 +88,155 @@ pub struct SearchLimits {
     pub max_depth: i32,
     pub max_time: i32,
     pub max_nodes: i32,
+    // playouts in this search, visits from an earlier search of a reused tree don't count,
+    // unlike max_nodes this doesn't depend on how deep the tree is
+    pub max_visits: i32,
+    // only these root moves are searched, all of them when empty
+    pub search_moves: Vec<Move>,
+    // look for a mate in this many moves, 0 for a normal search
+    pub mate: i32,
+    // search without limits until a ponderhit or stop arrives
+    pub ponder: bool,
+    // keep the bestmove back until a stop arrives, even if the search ends early
+    pub infinite: bool,
 }
 
 #[derive(Clone)]
 pub struct SearchResults {
     pub best_move: Move,
     pub nodes: u64,
+    pub visits: u32,
+    pub evals: u32,
     pub score: Score,
     pub visit_dist: Vec<(Move, f32)>,
+    // most visited reply to best_move, null when it hasn't been searched
+    pub ponder_move: Move,
+}
+
+// set by the input thread while a search is running
+#[derive(Default)]
+pub struct SearchSignals {
+    pub stop: AtomicBool,
+    pub ponderhit: AtomicBool,
+    // from the go command until its bestmove is printed
+    pub searching: AtomicBool,
+}
+
+impl SearchSignals {
+    pub fn start(&self) {
+        self.stop.store(false, Ordering::Relaxed);
+        self.ponderhit.store(false, Ordering::Relaxed);
+        self.searching.store(true, Ordering::Relaxed);
+    }
+}
+
+// search constants exposed as uci options so they can be tuned
+#[derive(Clone, Copy, Debug)]
+pub struct SearchParams {
+    pub root_cpuct: f32,
+    pub cpuct: f32,
+    pub eval_scale: f32,
+    pub draw_margin: f32,
+    pub root_policy_temp: f32,
+    pub time_divisor: f32,
+    pub inc_divisor: f32,
+    pub fpu_value: f32,
+    pub fpu_reduction: f32,
+    pub cpuct_base: f32,
+    pub cpuct_factor: f32,
+}
+
+impl SearchParams {
+    pub const COUNT: usize = 11;
+
+    // uci name, min, max and spsa step of each param, in field order
+    pub const TUNABLES: [(&'static str, f32, f32, f32); Self::COUNT] = [
+        ("RootCpuct", 0.05, 10.0, 0.1),
+        ("Cpuct", 0.05, 10.0, 0.07),
+        ("EvalScale", 50.0, 2000.0, 20.0),
+        ("DrawMargin", 0.0, 1000.0, 15.0),
+        ("RootPolicyTemp", 0.1, 10.0, 0.3),
+        ("TimeDivisor", 1.0, 100.0, 2.0),
+        ("IncDivisor", 1.0, 10.0, 0.2),
+        ("FpuValue", 0.0, 1.0, 0.05),
+        ("FpuReduction", 0.0, 2.0, 0.05),
+        ("CpuctBase", 100.0, 100000.0, 1000.0),
+        ("CpuctFactor", 0.0, 10.0, 0.1),
+    ];
+
+    // TimeDivisor and IncDivisor only change anything when searching on a clock
+    pub const CLOCK_PARAMS: [usize; 2] = [5, 6];
+
+    pub fn get(&self, idx: usize) -> f32 {
+        match idx {
+            0 => self.root_cpuct,
+            1 => self.cpuct,
+            2 => self.eval_scale,
+            3 => self.draw_margin,
+            4 => self.root_policy_temp,
+            5 => self.time_divisor,
+            6 => self.inc_divisor,
+            7 => self.fpu_value,
+            8 => self.fpu_reduction,
+            9 => self.cpuct_base,
+            10 => self.cpuct_factor,
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn set(&mut self, idx: usize, value: f32) {
+        let (_, min, max, _) = Self::TUNABLES[idx];
+        let value = value.clamp(min, max);
+        match idx {
+            0 => self.root_cpuct = value,
+            1 => self.cpuct = value,
+            2 => self.eval_scale = value,
+            3 => self.draw_margin = value,
+            4 => self.root_policy_temp = value,
+            5 => self.time_divisor = value,
+            6 => self.inc_divisor = value,
+            7 => self.fpu_value = value,
+            8 => self.fpu_reduction = value,
+            9 => self.cpuct_base = value,
+            10 => self.cpuct_factor = value,
+            _ => unreachable!(),
+        }
+    }
+
+    // returns false if no param has this name
+    pub fn set_by_name(&mut self, name: &str, value: f32) -> bool {
+        for (idx, (param_name, ..)) in Self::TUNABLES.iter().enumerate() {
+            if param_name.eq_ignore_ascii_case(name) {
+                self.set(idx, value);
+                return true;
+            }
+        }
+        false
+    }
+
+    pub fn print_uci_options(&self) {
+        for (idx, (name, ..)) in Self::TUNABLES.iter().enumerate() {
+            println!("option name {} type string default {}", name, self.get(idx));
+        }
+    }
+}
+
+impl Default for SearchParams {
+    fn default() -> Self {
+        Self {
+            root_cpuct: 1.10929019,
+            cpuct: 0.70710678,
+            eval_scale: 400.0,
+            draw_margin: 200.0,
+            root_policy_temp: 3.0,
+            time_divisor: 20.0,
+            inc_divisor: 2.0,
+            fpu_value: 0.5,
+            fpu_reduction: 0.0,
+            cpuct_base: 19652.0,
+            cpuct_factor: 0.0,
+        }
+    }
 }
 
 impl SearchLimits {
@@ -38,49 +248,194 @@ impl SearchLimits {
             max_depth: -1,
             max_time: -1,
             max_nodes: -1,
+            max_visits: -1,
+            search_moves: Vec::new(),
+            mate: 0,
+            ponder: false,
+            infinite: false,
         }
     }
 }
 
+// counters for a single search:
+// iters is the number of playouts, each adds one visit to the root
+// nodes is every position a playout passes through, root and leaf included, plus the positions
+// searched by qsearch, this is the nodes figure in info lines, go nodes, bench and datagen
+// evals is the number of leaf evaluations, terminal and proven leaves don't need one
 pub struct MCTS {
+    params: SearchParams,
     iters: u32,
     tree: Tree,
+    hash: u64,
+    large_pages: bool,
     root_position: Position,
     position: Position,
-    nodes: u32,
+    nodes: u64,
+    evals: u32,
+    seldepth: u32,
+    show_wdl: bool,
+    contempt: i32,
+    draw_value: f32,
+    flips: u32,
+    flip_kept: u64,
+    flip_total: u64,
+    qsearch: bool,
+    move_selection: MoveSelection,
+    move_visit_floor: u32,
+    root_fpu_mode: FpuMode,
+    fpu_mode: FpuMode,
+    qnodes: u64,
+    root_restricted: bool,
+    signals: Arc<SearchSignals>,
 }
 
 impl MCTS {
-    const ROOT_CPUCT: f32 = 1.10929019;
-    const CPUCT: f32 = 0.70710678;
-    const EVAL_SCALE: f32 = 400.0;
-
     pub fn new() -> Self {
         Self {
-            tree: Tree::new(24),
+            params: SearchParams::default(),
+            tree: Tree::new(24, true),
+            hash: 24,
+            large_pages: true,
             iters: 0,
             root_position: Position::new(),
             position: Position::new(),
             nodes: 0,
+            evals: 0,
+            seldepth: 0,
+            show_wdl: false,
+            contempt: 0,
+            draw_value: 0.5,
+            flips: 0,
+            flip_kept: 0,
+            flip_total: 0,
+            qsearch: false,
+            move_selection: MoveSelection::Q,
+            move_visit_floor: 0,
+            root_fpu_mode: FpuMode::Infinite,
+            fpu_mode: FpuMode::Reduction,
+            qnodes: 0,
+            root_restricted: false,
+            signals: Arc::new(SearchSignals::default()),
         }
     }
 
     pub fn set_hash(&mut self, hash: u64) {
-        self.tree = Tree::new(hash);
+        // free the old tree first so both are never allocated at once
+        self.tree = Tree::new(0, false);
+        self.tree = Tree::new(hash, self.large_pages);
+        self.hash = hash;
+    }
+
+    pub fn set_large_pages(&mut self, large_pages: bool) {
+        self.large_pages = large_pages;
+        self.set_hash(self.hash);
+    }
+
+    pub fn signals(&self) -> Arc<SearchSignals> {
+        Arc::clone(&self.signals)
+    }
+
+    pub fn hash_info(&self) -> String {
+        format!("{} MB hash using {}", self.hash, self.tree.alloc_strategy())
+    }
+
+    pub fn params(&self) -> &SearchParams {
+        &self.params
+    }
+
+    pub fn params_mut(&mut self) -> &mut SearchParams {
+        &mut self.params
+    }
+
+    // resolve captures at leaves with a small quiescence search before evaluating them
+    pub fn set_qsearch(&mut self, qsearch: bool) {
+        self.qsearch = qsearch;
+    }
+
+    pub fn set_fpu_mode(&mut self, root: bool, fpu_mode: FpuMode) {
+        if root {
+            self.root_fpu_mode = fpu_mode;
+        } else {
+            self.fpu_mode = fpu_mode;
+        }
+    }
+
+    pub fn set_move_selection(&mut self, move_selection: MoveSelection) {
+        self.move_selection = move_selection;
+    }
+
+    // percentage of the most visited child's visits below which q and lcb selection skip a child
+    pub fn set_move_visit_floor(&mut self, visit_floor: u32) {
+        self.move_visit_floor = visit_floor.min(100);
+    }
+
+    pub fn set_show_wdl(&mut self, show_wdl: bool) {
+        self.show_wdl = show_wdl;
+    }
+
+    // in centipawns, a draw is scored like an eval of -contempt for the root side
+    pub fn set_contempt(&mut self, contempt: i32) {
+        self.contempt = contempt;
     }
 
     pub fn new_game(&mut self) {
         self.tree.clear();
     }
 
-    fn eval_wdl(&self) -> f32 {
-        let board = self.position.board();
-        let eval = eval::eval(board);
+    pub fn save_tree(&self, filename: &str) -> Result<(), String> {
+        if self.tree.size() == 0 {
+            return Err("no tree to save".to_owned());
+        }
+        fs::write(filename, self.tree.save(&self.root_position)).map_err(|err| err.to_string())
+    }
+
+    // returns the root position of the loaded tree, searching it again reuses the tree
+    pub fn load_tree(&mut self, filename: &str) -> Result<Position, String> {
+        let bytes = fs::read(filename).map_err(|err| err.to_string())?;
+        let position = self.tree.load(&bytes)?;
+        self.root_position = position.clone();
+        self.root_restricted = false;
+        Ok(position)
+    }
 
-        sigmoid(eval as f32, Self::EVAL_SCALE)
+    // expected score for the side to move at a node, positive contempt makes draws worth less
+    // to the root side and more to its opponent
+    fn utility(&self, wdl: Wdl, root_side: bool) -> f32 {
+        let draw_value = if root_side {
+            self.draw_value
+        } else {
+            1.0 - self.draw_value
+        };
+        wdl.win + wdl.draw * draw_value
     }
 
-    fn simulate(&self, ply: i32) -> (f32, GameResult) {
+    // root score with contempt applied, the raw wdl is still available from the root node
+    fn root_score(&self) -> Score {
+        match self.tree[self.tree.root_node()].score() {
+            Score::Normal(wdl) => Score::Normal(wdl.with_draw_value(self.draw_value)),
+            score => score,
+        }
+    }
+
+    fn eval_wdl(&mut self) -> Wdl {
+        self.evals += 1;
+        let eval = if self.qsearch {
+            // qsearch works on a copy so nothing can leave the position out of sync with its keys
+            let mut board = self.position.board().clone();
+            let mut qnodes = 0;
+            let eval = qsearch::qsearch(&mut board, &mut qnodes);
+            // the leaf itself is already counted by the caller
+            self.qnodes += qnodes as u64 - 1;
+            self.nodes += qnodes as u64 - 1;
+            eval
+        } else {
+            eval::eval(self.position.board())
+        };
+
+        Wdl::from_cp(eval as f32, self.params.eval_scale, self.params.draw_margin)
+    }
+
+    fn simulate(&mut self, ply: i32) -> (Wdl, GameResult) {
         let mut moves = MoveList::new();
         movegen(self.position.board(), &mut moves);
 
@@ -97,8 +452,8 @@ impl MCTS {
         };
 
         match result {
-            GameResult::Drawn => (0.5, result),
-            GameResult::Mated => (0.0, result),
+            GameResult::Drawn => (Wdl::DRAW, result),
+            GameResult::Mated => (Wdl::LOSS, result),
             GameResult::NonTerminal => (self.eval_wdl(), result),
         }
     }
@@ -170,16 +525,93 @@ impl MCTS {
         }
     }
 
-    fn perform_one_impl(&mut self, node_idx: NodeIndex, ply: u32) -> Option<(f32, Option<i32>)> {
-        let root = node_idx == self.tree.root_node();
-        if self.tree[node_idx].is_terminal() || self.tree[node_idx].visits() == 0 {
+    // a node can do at least as well as its best pessimistic child and at most as well as its
+    // best optimistic one, children are negated as their bounds are for the other side
+    fn update_bounds(tree: &mut Tree, node_idx: NodeIndex) {
+        let mut lower = -1;
+        let mut upper = -1;
+        for child_idx in tree[node_idx].child_indices() {
+            let (child_lower, child_upper) = tree[child_idx].bounds();
+            lower = lower.max(-child_upper);
+            upper = upper.max(-child_lower);
+        }
+        tree[node_idx].set_bounds(lower, upper);
+    }
+
+    // first play urgency and cpuct for choosing among node's children, which are the same for
+    // every child so they are worked out once per node
+    fn select_consts(&self, node: &Node, ply: u32) -> (f32, f32) {
+        let root = ply == 0;
+        let fpu_mode = if root {
+            self.root_fpu_mode
+        } else {
+            self.fpu_mode
+        };
+        let fpu = match fpu_mode {
+            FpuMode::Infinite => 1000.0,
+            FpuMode::Absolute => self.params.fpu_value,
+            FpuMode::Reduction => {
+                // the more policy already explored, the less promising the rest are
+                let explored = if self.params.fpu_reduction > 0.0 {
+                    node.child_indices()
+                        .map(|child_idx| &self.tree[child_idx])
+                        .filter(|child| child.visits() > 0)
+                        .map(|child| child.policy())
+                        .sum::<f32>()
+                } else {
+                    0.0
+                };
+                self.utility(node.wdl(), ply.is_multiple_of(2))
+                    - self.params.fpu_reduction * explored.sqrt()
+            }
+        };
+
+        // grows slowly with visits like lc0 and alphazero, a factor of 0 keeps it constant
+        let cpuct_init = if root {
+            self.params.root_cpuct
+        } else {
+            self.params.cpuct
+        };
+        let cpuct = if self.params.cpuct_factor > 0.0 {
+            let growth =
+                ((node.visits() as f32 + self.params.cpuct_base) / self.params.cpuct_base).ln();
+            cpuct_init + self.params.cpuct_factor * growth
+        } else {
+            cpuct_init
+        };
+
+        (fpu, cpuct)
+    }
+
+    // q and uct of a child for the side to move at node, ply is the node's distance from the root
+    fn child_uct(&self, node: &Node, child: &Node, ply: u32, fpu: f32, cpuct: f32) -> (f32, f32) {
+        let q = if child.visits() == 0 {
+            fpu
+        } else {
+            // child wdl is flipped because it is from the opposite perspective of current node
+            self.utility(child.wdl().flip(), ply.is_multiple_of(2))
+        };
+        let policy = child.policy();
+        let expl = (node.visits() as f32).sqrt() / (1 + child.visits()) as f32;
+        (q, q + cpuct * policy * expl)
+    }
+
+    fn perform_one_impl(&mut self, node_idx: NodeIndex, ply: u32) -> Option<(Wdl, Option<i32>)> {
+        if !self.tree[node_idx].is_terminal() && self.tree[node_idx].is_proven_draw() {
+            // nothing left to learn below a proven draw
+            self.tree[node_idx].add_wdl(Wdl::DRAW);
+            self.nodes += ply as u64 + 1;
+            self.seldepth = self.seldepth.max(ply);
+            Some((Wdl::DRAW, None))
+        } else if self.tree[node_idx].is_terminal() || self.tree[node_idx].visits() == 0 {
             let (score, game_result) = self.simulate(ply as i32);
 
             let node = &mut self.tree[node_idx];
             node.set_game_result(game_result);
-            node.add_score(score);
+            node.add_wdl(score);
 
-            self.nodes += ply + 1;
+            self.nodes += ply as u64 + 1;
+            self.seldepth = self.seldepth.max(ply);
 
             return Some((
                 score,
@@ -192,31 +624,31 @@ impl MCTS {
         } else {
             // node can't be terminal here, must be unexpanded
             if self.tree[node_idx].child_count() == 0 {
-                self.tree.expand_node(node_idx, self.position.board())?;
+                self.tree.expand_node(
+                    node_idx,
+                    self.position.board(),
+                    self.params.root_policy_temp,
+                    &[],
+                )?;
             }
             self.tree.fetch_children(node_idx)?;
 
             let node = &self.tree[node_idx];
 
+            // children proven worse than what the node is already guaranteed are skipped, ones
+            // that only match it are kept so the backed up value doesn't drop below the bound
+            let (lower, upper) = node.bounds();
+            let prune = lower != upper;
+            let (fpu, cpuct) = self.select_consts(node, ply);
+
             let mut best_uct = -1f32;
             let mut best_child_idx = self.tree.root_node();
             for child_idx in node.child_indices() {
                 let child = &self.tree[child_idx];
-                let q = if child.visits() == 0 {
-                    if root {
-                        1000.0
-                    } else {
-                        node.q()
-                    }
-                } else {
-                    // 1 - child q because child q is from opposite perspective of current node
-                    1.0 - child.q()
-                };
-                let policy = child.policy();
-                let expl = (node.visits() as f32).sqrt() / (1 + child.visits()) as f32;
-                let cpuct = if root { Self::ROOT_CPUCT } else { Self::CPUCT };
-                let uct = q + cpuct * policy * expl;
-
+                if prune && -child.bounds().0 < lower {
+                    continue;
+                }
+                let (_, uct) = self.child_uct(node, child, ply, fpu, cpuct);
                 if uct > best_uct {
                     best_child_idx = child_idx;
                     best_uct = uct;
@@ -225,8 +657,14 @@ impl MCTS {
 
             self.position
                 .make_move(self.tree[best_child_idx].parent_move());
+            let child_bounds = self.tree[best_child_idx].bounds();
             let (child_score, mut child_mate_dist) =
                 self.perform_one_impl(best_child_idx, ply + 1)?;
+            self.position.unmake_move();
+
+            if self.tree[best_child_idx].bounds() != child_bounds {
+                Self::update_bounds(&mut self.tree, node_idx);
+            }
 
             if let Some(mate_dist) = child_mate_dist {
                 if mate_dist <= 0 {
@@ -236,75 +674,266 @@ impl MCTS {
                 }
             }
 
-            let score = 1.0 - child_score;
+            let score = child_score.flip();
 
             let node = &mut self.tree[node_idx];
 
-            node.add_score(score);
+            node.add_wdl(score);
 
             Some((score, child_mate_dist))
         }
     }
 
+    // each playout unmakes its moves on the way back up, so position is the root again after it
     fn perform_one_iter(&mut self) -> Result<(), ()> {
-        self.position = self.root_position.clone();
         if self.perform_one_impl(self.tree.root_node(), 0).is_none() {
+            // the playout stopped partway down
+            self.position.clone_from(&self.root_position);
             return Err(());
         }
         self.iters += 1;
         Ok(())
     }
 
-    fn pv_score(node: &Node) -> f32 {
+    fn pv_score(&self, node: &Node) -> f32 {
         match node.score().flip() {
             Score::Win(dist) => 1000.0 - dist as f32,
-            Score::Draw => 0.5,
+            Score::Draw => self.utility(Wdl::DRAW, true),
             Score::Loss(dist) => -1000.0 + dist as f32,
-            Score::Normal(score) => score,
+            Score::Normal(wdl) => self.utility(wdl, true),
         }
     }
 
+    // the floor is relative to the most visited unproven child, a proven loss can soak up visits
+    // without ever being a candidate
+    fn visit_floor(&self, max_visits: f32) -> f32 {
+        max_visits * self.move_visit_floor as f32 / 100.0
+    }
+
+    fn max_unproven_visits(&self, exclude: Option<NodeIndex>) -> u32 {
+        let root_node = &self.tree[self.tree.root_node()];
+        root_node
+            .child_indices()
+            .filter(|child_idx| Some(*child_idx) != exclude)
+            .map(|child_idx| &self.tree[child_idx])
+            .filter(|child_node| !matches!(child_node.score(), Score::Win(_) | Score::Loss(_)))
+            .map(|child_node| child_node.visits())
+            .max()
+            .unwrap_or(0)
+    }
+
     fn get_best_move(&self) -> Move {
         let root_node = &self.tree[self.tree.root_node()];
-        let mut best_score = -1000.0;
+        let visit_floor = self.visit_floor(self.max_unproven_visits(None) as f32);
+
+        let mut best_key = (0, f32::NEG_INFINITY);
         let mut best_move = Move::NULL;
         for child_idx in root_node.child_indices() {
             let child_node = &self.tree[child_idx];
             if child_node.visits() == 0 {
                 continue;
             }
-            let score = Self::pv_score(child_node);
-            if score > best_score {
-                best_score = score;
+            let score = self.pv_score(child_node);
+
+            // proven wins come first and proven losses last whatever the selection mode, moves
+            // under the visit floor are only played if there is nothing else but proven losses
+            let key = match child_node.score().flip() {
+                Score::Win(_) => (3, score),
+                Score::Loss(_) => (0, score),
+                _ if self.move_selection == MoveSelection::Visits => {
+                    (2, child_node.visits() as f32)
+                }
+                _ if (child_node.visits() as f32) < visit_floor => (1, score),
+                _ if self.move_selection == MoveSelection::Lcb => {
+                    let std_err = (child_node.score_variance() / child_node.visits() as f32).sqrt();
+                    (2, score - LCB_Z * std_err)
+                }
+                _ => (2, score),
+            };
+            if best_move == Move::NULL || key > best_key {
+                best_key = key;
                 best_move = child_node.parent_move();
             }
         }
         best_move
     }
 
-    fn display_tree_impl(&self, node_idx: NodeIndex, depth: i32, ply: i32) {
-        if depth <= 0 {
-            return;
+    // whether remaining more playouts can't change the move that would be played, this holds for
+    // visits and q selection but lcb is never treated as decided since its bound moves with the
+    // variance as well as the mean
+    fn best_move_decided(&self, remaining: f32) -> bool {
+        let root_node = &self.tree[self.tree.root_node()];
+        if root_node.child_count() == 1 {
+            return true;
         }
-        let indentation = || "    ".repeat(ply as usize);
-        let node = &self.tree[node_idx];
-        let mut children: Vec<NodeIndex> = node.child_indices().collect();
-        children.sort_by(|a, b| self.tree[*b].visits().cmp(&self.tree[*a].visits()));
-        for child_idx in children {
+        match self.move_selection {
+            MoveSelection::Visits => self.visits_decided(remaining),
+            MoveSelection::Q => self.q_decided(remaining),
+            MoveSelection::Lcb => false,
+        }
+    }
+
+    // the most visited move stays ahead even if every remaining playout goes to the runner up
+    fn visits_decided(&self, remaining: f32) -> bool {
+        let root_node = &self.tree[self.tree.root_node()];
+        let mut best = (0, Move::NULL);
+        let mut second_visits = 0;
+        for child_idx in root_node.child_indices() {
             let child_node = &self.tree[child_idx];
-            println!(
-                "{}{} => {} visits {}",
-                indentation(),
-                child_node.parent_move(),
-                child_node.visits(),
-                child_node.score()
-            );
-            self.display_tree_impl(child_idx, depth - 1, ply + 1);
+            if child_node.visits() > best.0 {
+                second_visits = best.0;
+                best = (child_node.visits(), child_node.parent_move());
+            } else {
+                second_visits = second_visits.max(child_node.visits());
+            }
+        }
+        // proven moves are picked ahead of the most visited one
+        best.1 == self.get_best_move() && (best.0 - second_visits) as f32 > remaining
+    }
+
+    // the best move keeps the highest q even if the remaining playouts all lose for it and all
+    // win for any other move, which overestimates how far they can go in both directions. it also
+    // has to stay above the visit floor if all of them go to the most visited other move
+    fn q_decided(&self, remaining: f32) -> bool {
+        let best_move = self.get_best_move();
+        let root_node = &self.tree[self.tree.root_node()];
+        let Some(best_idx) = root_node
+            .child_indices()
+            .find(|child_idx| self.tree[*child_idx].parent_move() == best_move)
+        else {
+            return false;
+        };
+        let best_node = &self.tree[best_idx];
+        let best_visits = best_node.visits() as f32;
+        let max_other_visits = self.max_unproven_visits(Some(best_idx));
+        if best_visits < self.visit_floor(max_other_visits as f32 + remaining) {
+            return false;
         }
+        let best_q = self.utility(best_node.wdl().flip(), true);
+        let q_floor = best_q * best_visits / (best_visits + remaining);
+
+        root_node
+            .child_indices()
+            .filter(|child_idx| *child_idx != best_idx)
+            .all(|child_idx| {
+                let child_node = &self.tree[child_idx];
+                let visits = child_node.visits() as f32;
+                let q_ceiling = match child_node.score().flip() {
+                    // proven losses are never played and a proven win would have ended the search
+                    Score::Loss(_) => return true,
+                    Score::Win(_) => return false,
+                    Score::Draw => self.utility(Wdl::DRAW, true),
+                    Score::Normal(wdl) => {
+                        (self.utility(wdl, true) * visits + remaining) / (visits + remaining)
+                    }
+                };
+                q_ceiling < q_floor
+            })
     }
 
-    pub fn display_tree(&self, depth: i32) {
-        self.display_tree_impl(self.tree.root_node(), depth, 0);
+    // the most visited reply to mv, which the gui can ponder on
+    fn get_ponder_move(&self, mv: Move) -> Move {
+        let root_node = &self.tree[self.tree.root_node()];
+        let Some(child_idx) = root_node
+            .child_indices()
+            .find(|child_idx| self.tree[*child_idx].parent_move() == mv)
+        else {
+            return Move::NULL;
+        };
+        self.tree[child_idx]
+            .child_indices()
+            .map(|reply_idx| &self.tree[reply_idx])
+            .filter(|reply| reply.visits() > 0)
+            .max_by_key(|reply| reply.visits())
+            .map_or(Move::NULL, |reply| reply.parent_move())
+    }
+
+    // children sorted by visits, leaving out those below min_visits
+    fn explore_children(&self, node_idx: NodeIndex, min_visits: u32) -> Vec<NodeIndex> {
+        let mut children: Vec<NodeIndex> = self.tree[node_idx]
+            .child_indices()
+            .filter(|child_idx| self.tree[*child_idx].visits() >= min_visits)
+            .collect();
+        children.sort_by(|a, b| self.tree[*b].visits().cmp(&self.tree[*a].visits()));
+        children
+    }
+
+    // children of the node reached by playing moves from the root, down to depth plies below it
+    pub fn explore_tree(
+        &self,
+        moves: &[&str],
+        depth: u32,
+        min_visits: u32,
+    ) -> Result<Vec<TreeRow>, String> {
+        if self.tree.size() == 0 {
+            return Err("no tree to explore".to_owned());
+        }
+        let mut node_idx = self.tree.root_node();
+        let mut board = self.root_position.board().clone();
+        for mv_str in moves {
+            let mv = notation::parse_move(&board, mv_str)
+                .ok_or_else(|| format!("invalid move {}", mv_str))?;
+            node_idx = self.tree[node_idx]
+                .child_indices()
+                .find(|child_idx| self.tree[*child_idx].parent_move() == mv)
+                .ok_or_else(|| format!("move {} is not in the tree", mv_str))?;
+            board.make_move(mv);
+        }
+        let root_ply = moves.len() as u32;
+
+        // depth first with an explicit stack so rows come out in print order
+        let mut rows = Vec::new();
+        let mut stack = Vec::new();
+        if depth > 0 {
+            for child_idx in self
+                .explore_children(node_idx, min_visits)
+                .into_iter()
+                .rev()
+            {
+                stack.push((None, node_idx, child_idx, board.clone(), 0));
+            }
+        }
+        while let Some((parent, node_idx, child_idx, board, row_depth)) = stack.pop() {
+            let node = &self.tree[node_idx];
+            let child_node = &self.tree[child_idx];
+            let mv = child_node.parent_move();
+            let ply = root_ply + row_depth;
+            let (fpu, cpuct) = self.select_consts(node, ply);
+            let (q, uct) = self.child_uct(node, child_node, ply, fpu, cpuct);
+            rows.push(TreeRow {
+                parent,
+                depth: row_depth,
+                san: notation::move_to_san(&board, mv),
+                visits: child_node.visits(),
+                share: child_node.visits() as f32 / node.visits().max(1) as f32,
+                q,
+                policy: child_node.policy(),
+                uct,
+                score: child_node.score().flip(),
+                proven: child_node.is_proven(),
+                inactive: child_idx.half() != self.tree.root_node().half(),
+            });
+
+            if row_depth + 1 < depth {
+                let row = rows.len() - 1;
+                let mut child_board = board;
+                child_board.make_move(mv);
+                for grandchild_idx in self
+                    .explore_children(child_idx, min_visits)
+                    .into_iter()
+                    .rev()
+                {
+                    stack.push((
+                        Some(row),
+                        child_idx,
+                        grandchild_idx,
+                        child_board.clone(),
+                        row_depth + 1,
+                    ));
+                }
+            }
+        }
+        Ok(rows)
     }
 
     fn get_visit_dist(&self) -> Vec<(Move, f32)> {
@@ -325,6 +954,9 @@ impl MCTS {
         if self.tree.size() == 0 {
             return NodeIndex::NULL;
         }
+        if *position == self.root_position {
+            return self.tree.root_node();
+        }
         let root_node = &self.tree[self.tree.root_node()];
         for child_idx in root_node.child_indices() {
             let child_node = &self.tree[child_idx];
@@ -342,102 +974,219 @@ impl MCTS {
         NodeIndex::NULL
     }
 
+    // whether the root is a proven win in at most moves moves
+    fn mate_found(&self, moves: i32) -> bool {
+        match self.root_score() {
+            Score::Win(dist) => (dist as i32 + 1) / 2 <= moves,
+            _ => false,
+        }
+    }
+
+    // go nodes and go visits stop before a playout that would start over the limit, at least one
+    // is always run so there is a move to play
+    fn limit_reached(&self, limits: &SearchLimits) -> bool {
+        self.iters > 0
+            && ((limits.max_nodes >= 0 && self.nodes >= limits.max_nodes as u64)
+                || (limits.max_visits >= 0 && self.iters >= limits.max_visits as u32))
+    }
+
     fn depth(&self) -> u32 {
-        (self.nodes - self.iters) / self.iters.max(1)
+        ((self.nodes - self.qnodes - self.iters as u64) / self.iters.max(1) as u64) as u32
+    }
+
+    fn hashfull(&self) -> u32 {
+        let half = self.tree.curr_half();
+        (half.used_nodes() as u64 * 1000 / half.max_nodes().max(1) as u64) as u32
+    }
+
+    fn print_info(&self, start_time: Instant) {
+        let elapsed = start_time.elapsed().as_secs_f64();
+        let score = self.root_score();
+        let wdl = if self.show_wdl {
+            let (win, draw, loss) = self.tree[self.tree.root_node()].score().wdl();
+            format!(" wdl {} {} {}", win, draw, loss)
+        } else {
+            String::new()
+        };
+        println!(
+            "info depth {} seldepth {} nodes {} time {} nps {} hashfull {} score {}{} pv {}",
+            self.depth(),
+            self.seldepth,
+            self.nodes,
+            (elapsed * 1000.0) as u64,
+            (self.nodes as f64 / elapsed) as u64,
+            self.hashfull(),
+            score.uci_str(),
+            wdl,
+            notation::move_to_uci(self.root_position.board(), self.get_best_move())
+        );
     }
 
     pub fn run(
         &mut self,
-        limits: SearchLimits,
+        limits: &SearchLimits,
         report: bool,
         position: &Position,
     ) -> SearchResults {
-        let new_root_idx = self.find_node(position);
+        let mut new_root_idx = self.find_node(position);
+        // a root expanded with searchmoves is missing children so it can't be reused, and any node
+        // found in the tree has all of its children so searchmoves always starts a new tree
+        if !limits.search_moves.is_empty()
+            || (self.root_restricted && new_root_idx == self.tree.root_node())
+        {
+            new_root_idx = NodeIndex::NULL;
+        }
 
         self.root_position = position.clone();
         self.position = self.root_position.clone();
         self.iters = 0;
         self.nodes = 0;
+        self.evals = 0;
+        self.seldepth = 0;
+        self.qnodes = 0;
+        self.flips = 0;
+        self.flip_kept = 0;
+        self.flip_total = 0;
+        self.draw_value = sigmoid(-self.contempt as f32, self.params.eval_scale);
 
         if new_root_idx != NodeIndex::NULL && self.tree[new_root_idx].child_count() > 0 {
             self.tree.set_as_root(new_root_idx);
-            self.tree
-                .relabel_policies(self.tree.root_node(), &self.root_position.board());
+            self.tree.relabel_policies(
+                self.tree.root_node(),
+                &self.root_position.board(),
+                self.params.root_policy_temp,
+            );
         } else {
             self.tree.clear();
             self.tree.add_root_node();
             self.tree
-                .expand_node(self.tree.root_node(), self.root_position.board())
+                .expand_node(
+                    self.tree.root_node(),
+                    self.root_position.board(),
+                    self.params.root_policy_temp,
+                    &limits.search_moves,
+                )
                 .expect("Cannot expand root node in tree");
             let eval = self.eval_wdl();
             let root = self.tree.root_node();
-            self.tree[root].add_score(eval);
+            self.tree[root].add_wdl(eval);
         }
+        self.root_restricted = !limits.search_moves.is_empty();
 
         let mut prev_depth = 0;
 
         let start_time = Instant::now();
+        let mut last_info = start_time;
+        // time limits count from the ponderhit when pondering
+        let mut clock_start = start_time;
+        let mut pondering = limits.ponder;
 
-        while limits.max_nodes < 0 || self.iters <= limits.max_nodes as u32 {
+        while !self.limit_reached(limits) {
             let result = self.perform_one_iter();
             if result.is_err() {
-                self.tree.flip();
+                let (kept, total) = self.tree.flip();
+                self.flips += 1;
+                self.flip_kept += kept as u64;
+                self.flip_total += total as u64;
                 continue;
             }
 
+            // a solved root won't change with more search, which also ends a mate search
+            if self.tree[self.tree.root_node()].is_proven() {
+                break;
+            }
+
             let curr_depth = self.depth();
             if curr_depth > prev_depth {
-                if limits.max_depth > 0 && curr_depth >= limits.max_depth as u32 {
+                if !pondering && limits.max_depth > 0 && curr_depth >= limits.max_depth as u32 {
                     break;
                 }
 
                 prev_depth = curr_depth;
                 if report {
-                    let elapsed = start_time.elapsed().as_secs_f64();
-                    println!(
-                        "info depth {} nodes {} time {} nps {} score {} pv {}",
-                        curr_depth,
-                        self.nodes,
-                        (elapsed * 1000.0) as u64,
-                        (self.nodes as f64 / elapsed as f64) as u64,
-                        self.tree[self.tree.root_node()].score().uci_str(),
-                        self.get_best_move()
-                    );
+                    self.print_info(start_time);
+                    last_info = Instant::now();
                 }
             }
 
             // don't check every iter
             if self.iters % 512 == 0 {
-                let elapsed = start_time.elapsed().as_secs_f64();
+                // keep the gui updated when the depth is slow to increase
+                if report && last_info.elapsed().as_millis() >= INFO_INTERVAL_MS {
+                    self.print_info(start_time);
+                    last_info = Instant::now();
+                }
+
+                if self.signals.stop.load(Ordering::Relaxed) {
+                    break;
+                }
+                if pondering {
+                    if !self.signals.ponderhit.load(Ordering::Relaxed) {
+                        continue;
+                    }
+                    pondering = false;
+                    clock_start = Instant::now();
+                }
+
+                let elapsed = clock_start.elapsed().as_secs_f64();
                 let elapsed_ms = (elapsed * 1000.0) as i32;
                 if limits.max_time >= 0 && elapsed_ms >= limits.max_time {
                     break;
                 }
 
-                if limits.use_clock && elapsed_ms >= limits.time / 20 + limits.inc / 2 {
-                    break;
+                if limits.use_clock {
+                    let allotted_ms = limits.time as f32 / self.params.time_divisor
+                        + limits.inc as f32 / self.params.inc_divisor;
+                    if elapsed_ms as f32 >= allotted_ms {
+                        break;
+                    }
+
+                    // the rest of the allotted time is kept for later moves
+                    let playout_rate = self.iters as f32 / start_time.elapsed().as_secs_f32();
+                    let remaining = playout_rate * (allotted_ms - elapsed_ms as f32) / 1000.0;
+                    if self.best_move_decided(remaining) {
+                        break;
+                    }
                 }
             }
         }
 
+        // uci doesn't allow a bestmove before the stop in an infinite search, or before the
+        // ponderhit when pondering
+        while !self.signals.stop.load(Ordering::Relaxed)
+            && (limits.infinite || pondering && !self.signals.ponderhit.load(Ordering::Relaxed))
+        {
+            thread::sleep(Duration::from_millis(1));
+        }
+
         if report {
-            let curr_depth = self.depth();
-            let elapsed = start_time.elapsed().as_secs_f64();
+            self.print_info(start_time);
             println!(
-                "info depth {} nodes {} time {} nps {} score {} pv {}",
-                curr_depth,
-                self.nodes,
-                (elapsed * 1000.0) as u64,
-                (self.nodes as f64 / elapsed as f64) as u64,
-                self.tree[self.tree.root_node()].score().uci_str(),
-                self.get_best_move()
+                "info string {} playouts {} evals {} qsearch nodes",
+                self.iters, self.evals, self.qnodes
             );
+            if limits.mate > 0 && !self.mate_found(limits.mate) {
+                println!("info string no mate in {} found", limits.mate);
+            }
+            if self.flips > 0 {
+                println!(
+                    "info string tree flipped {} times, kept {} of {} nodes ({:.1}%)",
+                    self.flips,
+                    self.flip_kept,
+                    self.flip_total,
+                    self.flip_kept as f64 * 100.0 / self.flip_total as f64
+                );
+            }
         }
 
+        let best_move = self.get_best_move();
         SearchResults {
-            best_move: self.get_best_move(),
-            nodes: self.nodes as u64,
-            score: self.tree[self.tree.root_node()].score(),
+            best_move,
+            ponder_move: self.get_ponder_move(best_move),
+            nodes: self.nodes,
+            visits: self.iters,
+            evals: self.evals,
+            score: self.root_score(),
             visit_dist: self.get_visit_dist(),
         }
     }
@@ -0,0 +1,277 @@
+use std::time::Instant;
+
+use rand::Rng;
+use rand_xorshift::XorShiftRng;
+
+use crate::{
+    chess::{
+        movegen::{self, MoveList},
+        Board, Move,
+    },
+    position::Position,
+    score::GameResult,
+    search::{SearchLimits, MCTS},
+    types::Color,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    WhiteWin,
+    Draw,
+    BlackWin,
+}
+
+pub fn game_result(pos: &Position) -> GameResult {
+    let mut moves = MoveList::new();
+    movegen::movegen(pos.board(), &mut moves);
+
+    if moves.len() == 0 {
+        if pos.board().checkers().any() {
+            GameResult::Mated
+        } else {
+            GameResult::Drawn
+        }
+    } else if pos.is_drawn(0) {
+        GameResult::Drawn
+    } else {
+        GameResult::NonTerminal
+    }
+}
+
+pub fn init_opening(rng: &mut XorShiftRng) -> Position {
+    'new_opening: loop {
+        let mut pos = Position::new();
+        for _ in 0..8 {
+            let mut moves = MoveList::new();
+            movegen::movegen(pos.board(), &mut moves);
+
+            let idx = rng.random_range(0..moves.len());
+            pos.make_move(moves[idx]);
+            if game_result(&pos) != GameResult::NonTerminal {
+                continue 'new_opening;
+            }
+        }
+        return pos;
+    }
+}
+
+// limits for a single move, the clocks are indexed by color
+#[derive(Debug, Clone, Copy)]
+pub struct GoLimits {
+    pub nodes: i32,
+    pub visits: i32,
+    pub time: [i32; 2],
+    pub inc: [i32; 2],
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum TimeControl {
+    Nodes(i32),
+    Visits(i32),
+    // base time and increment in milliseconds
+    Clock(i32, i32),
+}
+
+// cutechess style adjudication, a count of 0 disables the rule
+#[derive(Debug, Clone, Copy)]
+pub struct Adjudication {
+    // draw once both sides have reported |score| <= draw_score for draw_move_count
+    // moves each, starting from draw_move_number
+    pub draw_move_number: u32,
+    pub draw_move_count: u32,
+    pub draw_score: i32,
+    // a side loses once it has reported score <= -win_score for win_move_count moves in a row
+    pub win_move_count: u32,
+    pub win_score: i32,
+}
+
+impl Adjudication {
+    pub const NONE: Self = Self {
+        draw_move_number: 0,
+        draw_move_count: 0,
+        draw_score: 0,
+        win_move_count: 0,
+        win_score: 0,
+    };
+}
+
+pub trait Player {
+    fn new_game(&mut self);
+
+    // returns the chosen move and the score in centipawns for the side to move if one was reported
+    fn go(
+        &mut self,
+        opening: &Position,
+        moves: &[Move],
+        pos: &Position,
+        limits: GoLimits,
+    ) -> (Move, Option<i32>);
+}
+
+impl Player for MCTS {
+    fn new_game(&mut self) {
+        MCTS::new_game(self);
+    }
+
+    fn go(
+        &mut self,
+        _opening: &Position,
+        _moves: &[Move],
+        pos: &Position,
+        limits: GoLimits,
+    ) -> (Move, Option<i32>) {
+        let stm = pos.board().stm() as usize;
+        let mut search_limits = SearchLimits::new();
+        if limits.nodes > 0 {
+            search_limits.max_nodes = limits.nodes;
+        }
+        if limits.visits > 0 {
+            search_limits.max_visits = limits.visits;
+        }
+        if limits.time[stm] >= 0 {
+            search_limits.use_clock = true;
+            search_limits.time = limits.time[stm];
+            search_limits.inc = limits.inc[stm];
+        }
+        let results = self.run(&search_limits, false, pos);
+        (results.best_move, Some(results.score.cp()))
+    }
+}
+
+pub struct GameRecord {
+    pub moves: Vec<Move>,
+    pub scores: Vec<Option<i32>>,
+    pub outcome: Outcome,
+    pub reason: &'static str,
+}
+
+fn is_legal(board: &Board, mv: Move) -> bool {
+    let mut moves = MoveList::new();
+    movegen::movegen(board, &mut moves);
+    moves.contains(&mv)
+}
+
+fn loss_for(color: Color) -> Outcome {
+    if color == Color::White {
+        Outcome::BlackWin
+    } else {
+        Outcome::WhiteWin
+    }
+}
+
+// plays out a game between two players from the given opening
+pub fn play_game(
+    white: &mut dyn Player,
+    black: &mut dyn Player,
+    opening: &Position,
+    tc: TimeControl,
+    adjudication: &Adjudication,
+) -> GameRecord {
+    white.new_game();
+    black.new_game();
+
+    let mut limits = GoLimits {
+        nodes: -1,
+        visits: -1,
+        time: [-1; 2],
+        inc: [0; 2],
+    };
+    match tc {
+        TimeControl::Nodes(nodes) => limits.nodes = nodes,
+        TimeControl::Visits(visits) => limits.visits = visits,
+        TimeControl::Clock(time, inc) => {
+            limits.time = [time; 2];
+            limits.inc = [inc; 2];
+        }
+    }
+
+    let mut record = GameRecord {
+        moves: Vec::new(),
+        scores: Vec::new(),
+        outcome: Outcome::Draw,
+        reason: "",
+    };
+    let mut draw_plies = 0;
+    let mut losing_moves = [0; 2];
+
+    let mut pos = opening.clone();
+    loop {
+        let stm = pos.board().stm();
+        let player: &mut dyn Player = if stm == Color::White {
+            &mut *white
+        } else {
+            &mut *black
+        };
+
+        let start_time = Instant::now();
+        let (mv, score) = player.go(opening, &record.moves, &pos, limits);
+        let elapsed = start_time.elapsed().as_millis() as i32;
+
+        if let TimeControl::Clock(..) = tc {
+            if elapsed > limits.time[stm as usize] {
+                record.outcome = loss_for(stm);
+                record.reason = "loses on time";
+                return record;
+            }
+            limits.time[stm as usize] += limits.inc[stm as usize] - elapsed;
+        }
+
+        if !is_legal(pos.board(), mv) {
+            record.outcome = loss_for(stm);
+            record.reason = "illegal move";
+            return record;
+        }
+
+        pos.make_move(mv);
+        record.moves.push(mv);
+        record.scores.push(score);
+
+        match game_result(&pos) {
+            GameResult::Drawn => {
+                record.outcome = Outcome::Draw;
+                record.reason = "draw";
+                return record;
+            }
+            GameResult::Mated => {
+                record.outcome = loss_for(pos.board().stm());
+                record.reason = "checkmate";
+                return record;
+            }
+            GameResult::NonTerminal => {}
+        }
+
+        // an engine that doesn't report a score can't be adjudicated
+        let Some(score) = score else {
+            draw_plies = 0;
+            losing_moves[stm as usize] = 0;
+            continue;
+        };
+
+        if score.abs() <= adjudication.draw_score {
+            draw_plies += 1;
+        } else {
+            draw_plies = 0;
+        }
+        if adjudication.draw_move_count > 0
+            && record.moves.len() as u32 >= 2 * adjudication.draw_move_number
+            && draw_plies >= 2 * adjudication.draw_move_count
+        {
+            record.outcome = Outcome::Draw;
+            record.reason = "draw by adjudication";
+            return record;
+        }
+
+        if score <= -adjudication.win_score {
+            losing_moves[stm as usize] += 1;
+        } else {
+            losing_moves[stm as usize] = 0;
+        }
+        if adjudication.win_move_count > 0
+            && losing_moves[stm as usize] >= adjudication.win_move_count
+        {
+            record.outcome = loss_for(stm);
+            record.reason = "win by adjudication";
+            return record;
+        }
+    }
+}
@@ -0,0 +1,288 @@
+use std::{
+    fs::File,
+    io::Write,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use rand::Rng;
+use rand_core::{RngCore, SeedableRng};
+use rand_xorshift::XorShiftRng;
+
+use crate::{
+    match_runner::parse_time_control,
+    search::{SearchParams, MCTS},
+    selfplay::{init_opening, play_game, Adjudication, Outcome, TimeControl},
+};
+
+const N: usize = SearchParams::COUNT;
+
+// fishtest style spsa constants
+const ALPHA: f64 = 0.602;
+const GAMMA: f64 = 0.101;
+const R_END: f64 = 0.002;
+
+struct SpsaConfig {
+    iterations: u32,
+    tc: TimeControl,
+    threads: u32,
+    log_filename: String,
+}
+
+struct SpsaState {
+    theta: [f64; N],
+    next_iter: u32,
+    pairs: u32,
+    plus_wins: u32,
+    minus_wins: u32,
+    log_file: File,
+}
+
+struct Perturbation {
+    iter: u32,
+    delta: [f64; N],
+    c_k: [f64; N],
+    r_k: [f64; N],
+    plus: SearchParams,
+    minus: SearchParams,
+}
+
+fn parse_config(args: &[String]) -> SpsaConfig {
+    let mut config = SpsaConfig {
+        iterations: 10000,
+        tc: TimeControl::Visits(2000),
+        threads: 1,
+        log_filename: "spsa.log".to_owned(),
+    };
+
+    let mut tokens = args.iter();
+    while let Some(key) = tokens.next() {
+        let Some(value) = tokens.next() else {
+            println!("missing value for {}", key);
+            break;
+        };
+        match key.as_str() {
+            "iters" => config.iterations = value.parse().expect("Cannot parse iters"),
+            "nodes" => config.tc = TimeControl::Nodes(value.parse().expect("Cannot parse nodes")),
+            "visits" => {
+                config.tc = TimeControl::Visits(value.parse().expect("Cannot parse visits"))
+            }
+            "tc" => config.tc = parse_time_control(value),
+            "threads" => config.threads = value.parse().expect("Cannot parse threads"),
+            "log" => config.log_filename = value.clone(),
+            _ => println!("unknown spsa option {}", key),
+        }
+    }
+    config
+}
+
+// the clock params are only tuned in clock games, otherwise their result is noise
+fn is_tuned(config: &SpsaConfig, idx: usize) -> bool {
+    matches!(config.tc, TimeControl::Clock(..)) || !SearchParams::CLOCK_PARAMS.contains(&idx)
+}
+
+fn params_from_theta(theta: &[f64; N]) -> SearchParams {
+    let mut params = SearchParams::default();
+    for (i, value) in theta.iter().enumerate() {
+        params.set(i, *value as f32);
+    }
+    params
+}
+
+fn perturb(state: &mut SpsaState, config: &SpsaConfig, rng: &mut XorShiftRng) -> Perturbation {
+    let iter = state.next_iter;
+    state.next_iter += 1;
+
+    let k = iter as f64;
+    let total = config.iterations as f64;
+    let big_a = 0.1 * total;
+
+    let mut result = Perturbation {
+        iter,
+        delta: [0.0; N],
+        c_k: [0.0; N],
+        r_k: [0.0; N],
+        plus: SearchParams::default(),
+        minus: SearchParams::default(),
+    };
+
+    for i in 0..N {
+        let (_, _, _, c_end) = SearchParams::TUNABLES[i];
+        let c_end = c_end as f64;
+        let c = c_end * total.powf(GAMMA);
+        let a = R_END * c_end * c_end * (big_a + total).powf(ALPHA);
+
+        result.c_k[i] = c / k.powf(GAMMA);
+        let a_k = a / (big_a + k).powf(ALPHA);
+        result.r_k[i] = a_k / (result.c_k[i] * result.c_k[i]);
+        result.delta[i] = if !is_tuned(config, i) {
+            0.0
+        } else if rng.random_bool(0.5) {
+            1.0
+        } else {
+            -1.0
+        };
+
+        let offset = result.c_k[i] * result.delta[i];
+        result.plus.set(i, (state.theta[i] + offset) as f32);
+        result.minus.set(i, (state.theta[i] - offset) as f32);
+    }
+
+    result
+}
+
+fn log_iteration(state: &mut SpsaState, iter: u32, score: i32) {
+    let mut line = format!("iter {} score {}", iter, score);
+    for (i, (name, ..)) in SearchParams::TUNABLES.iter().enumerate() {
+        line += format!(" {} {:.5}", name, state.theta[i]).as_str();
+    }
+    line += "\n";
+    state
+        .log_file
+        .write_all(line.as_bytes())
+        .expect("Unable to write spsa log");
+    state.log_file.flush().expect("Unable to write spsa log");
+}
+
+fn spsa_thread(state: Arc<Mutex<SpsaState>>, config: Arc<SpsaConfig>, thread_id: u32) {
+    let seed = rand::rng().next_u64();
+    println!("Thread {} RNG seed: {}", thread_id, seed);
+    let mut rng = XorShiftRng::seed_from_u64(seed);
+
+    let mut plus_searcher = MCTS::new();
+    let mut minus_searcher = MCTS::new();
+
+    let tc = config.tc;
+
+    loop {
+        let perturbation = {
+            let mut state = state.lock().unwrap();
+            if state.next_iter > config.iterations {
+                return;
+            }
+            perturb(&mut state, &config, &mut rng)
+        };
+
+        *plus_searcher.params_mut() = perturbation.plus;
+        *minus_searcher.params_mut() = perturbation.minus;
+
+        // a game pair with colors reversed from the same opening
+        let opening = init_opening(&mut rng);
+        let first = play_game(
+            &mut plus_searcher,
+            &mut minus_searcher,
+            &opening,
+            tc,
+            &Adjudication::NONE,
+        );
+        let second = play_game(
+            &mut minus_searcher,
+            &mut plus_searcher,
+            &opening,
+            tc,
+            &Adjudication::NONE,
+        );
+
+        let mut plus_wins = 0;
+        let mut minus_wins = 0;
+        match first.outcome {
+            Outcome::WhiteWin => plus_wins += 1,
+            Outcome::BlackWin => minus_wins += 1,
+            Outcome::Draw => {}
+        }
+        match second.outcome {
+            Outcome::WhiteWin => minus_wins += 1,
+            Outcome::BlackWin => plus_wins += 1,
+            Outcome::Draw => {}
+        }
+        let score = plus_wins - minus_wins;
+
+        let mut state = state.lock().unwrap();
+        for i in 0..N {
+            let (_, min, max, _) = SearchParams::TUNABLES[i];
+            let step =
+                perturbation.r_k[i] * perturbation.c_k[i] * score as f64 * perturbation.delta[i];
+            state.theta[i] = (state.theta[i] + step).clamp(min as f64, max as f64);
+        }
+        state.pairs += 1;
+        state.plus_wins += plus_wins as u32;
+        state.minus_wins += minus_wins as u32;
+        log_iteration(&mut state, perturbation.iter, score);
+    }
+}
+
+pub fn run_spsa(args: &[String]) {
+    let config = Arc::new(parse_config(args));
+    let defaults = SearchParams::default();
+
+    let mut theta = [0.0; N];
+    for (i, value) in theta.iter_mut().enumerate() {
+        *value = defaults.get(i) as f64;
+    }
+
+    let state = Arc::new(Mutex::new(SpsaState {
+        theta,
+        next_iter: 1,
+        pairs: 0,
+        plus_wins: 0,
+        minus_wins: 0,
+        log_file: File::create(&config.log_filename).expect("Unable to create spsa log file"),
+    }));
+
+    let limit = match config.tc {
+        TimeControl::Nodes(nodes) => format!("{} nodes per move", nodes),
+        TimeControl::Visits(visits) => format!("{} visits per move", visits),
+        TimeControl::Clock(time, inc) => format!("tc {}ms+{}ms", time, inc),
+    };
+    println!(
+        "Running {} spsa iterations at {} on {} threads, logging to {}",
+        config.iterations, limit, config.threads, config.log_filename
+    );
+
+    let start_time = Instant::now();
+    let mut handles = Vec::new();
+    for i in 0..config.threads {
+        let state = state.clone();
+        let config = config.clone();
+        handles.push(thread::spawn(move || {
+            spsa_thread(state, config, i);
+        }));
+    }
+
+    let mut last_report = Instant::now();
+    loop {
+        thread::sleep(Duration::from_millis(100));
+        let finished = handles.iter().all(|handle| handle.is_finished());
+        if finished || last_report.elapsed().as_secs() >= 10 {
+            last_report = Instant::now();
+            let state = state.lock().unwrap();
+            let params = params_from_theta(&state.theta);
+            print!(
+                "{} pairs in {:.0}s, +{} -{}:",
+                state.pairs,
+                start_time.elapsed().as_secs_f32(),
+                state.plus_wins,
+                state.minus_wins
+            );
+            for (i, (name, ..)) in SearchParams::TUNABLES.iter().enumerate() {
+                print!(" {} {:.4}", name, params.get(i));
+            }
+            println!();
+        }
+        if finished {
+            break;
+        }
+    }
+
+    for handle in handles {
+        let _ = handle.join();
+    }
+
+    let state = state.lock().unwrap();
+    let params = params_from_theta(&state.theta);
+    println!("Final params:");
+    for (i, (name, ..)) in SearchParams::TUNABLES.iter().enumerate() {
+        println!("setoption name {} value {}", name, params.get(i));
+    }
+}
@@ -1,4 +1,5 @@
 use std::{
+    collections::BinaryHeap,
     num::NonZeroI16,
     ops::{Index, IndexMut},
 };
@@ -8,12 +9,22 @@ use arrayvec::ArrayVec;
 use crate::{
     chess::{
         movegen::{self, MoveList},
-        Board, Move,
+        Board, Move, ZobristKey,
     },
+    large_alloc::{AllocStrategy, LargeBuffer, Zeroable},
     policy,
-    score::{GameResult, MateScore, Score},
+    position::Position,
+    score::{GameResult, MateScore, Score, Wdl},
 };
 
+// share of the new half filled with the most visited part of the old tree on a flip,
+// the rest is left free for the search to grow into
+const FLIP_RETAIN_FRACTION: f64 = 0.5;
+
+// saved tree files start with the magic and version and end with a checksum of everything before it
+const TREE_MAGIC: &[u8; 6] = b"AQTREE";
+const TREE_VERSION: u32 = 3;
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct NodeIndex(u32);
 
@@ -85,11 +96,22 @@ pub struct Node {
     parent_move: Move,
     result: GameResult,
     mate_dist: Option<NonZeroI16>,
+    // proven bounds on the result for the side to move, -1 is a loss, 0 a draw and 1 a win
+    lower: i8,
+    upper: i8,
     policy: f32,
     wins: f32,
+    draws: f32,
+    // sum of squared expected scores, for the variance
+    score_sq: f32,
     visits: u32,
 }
 
+// every field is plain data so all zeros is a valid bit pattern, but not a usable node since
+// its (0, 0) bounds read as a proven draw. every node is written with Node::new before it is read,
+// the zeroing only lets a half be allocated without touching its memory
+unsafe impl Zeroable for Node {}
+
 impl Node {
     fn new(mv: Move, policy: f32) -> Self {
         Node {
@@ -98,14 +120,24 @@ impl Node {
             parent_move: mv,
             result: GameResult::NonTerminal,
             mate_dist: None,
+            lower: -1,
+            upper: 1,
             policy: policy,
             wins: 0.0,
+            draws: 0.0,
+            score_sq: 0.0,
             visits: 0,
         }
     }
 
-    pub fn q(&self) -> f32 {
-        self.wins / self.visits as f32
+    pub fn wdl(&self) -> Wdl {
+        let win = self.wins / self.visits as f32;
+        let draw = self.draws / self.visits as f32;
+        Wdl {
+            win,
+            draw,
+            loss: (1.0 - win - draw).max(0.0),
+        }
     }
 
     pub fn mate_score(&self) -> Option<MateScore> {
@@ -123,8 +155,32 @@ impl Node {
         }
     }
 
+    // mates and terminal draws are exact, otherwise whatever was proven from the children
+    pub fn bounds(&self) -> (i8, i8) {
+        match self.mate_score() {
+            Some(MateScore::Win(_)) => (1, 1),
+            Some(MateScore::Loss(_)) => (-1, -1),
+            None if self.game_result() == GameResult::Drawn => (0, 0),
+            None => (self.lower, self.upper),
+        }
+    }
+
+    pub fn is_proven(&self) -> bool {
+        let (lower, upper) = self.bounds();
+        lower == upper
+    }
+
+    pub fn is_proven_draw(&self) -> bool {
+        self.bounds() == (0, 0)
+    }
+
+    pub fn set_bounds(&mut self, lower: i8, upper: i8) {
+        self.lower = lower;
+        self.upper = upper;
+    }
+
     pub fn score(&self) -> Score {
-        if self.game_result() == GameResult::Drawn {
+        if self.is_proven_draw() {
             Score::Draw
         } else if let Some(mate_score) = self.mate_score() {
             match mate_score {
@@ -132,7 +188,7 @@ impl Node {
                 MateScore::Win(dist) => Score::Win(dist),
             }
         } else {
-            Score::Normal(self.q())
+            Score::Normal(self.wdl())
         }
     }
 
@@ -167,9 +223,18 @@ impl Node {
         self.policy
     }
 
-    pub fn add_score(&mut self, score: f32) {
+    pub fn add_wdl(&mut self, wdl: Wdl) {
         self.visits += 1;
-        self.wins += score;
+        self.wins += wdl.win;
+        self.draws += wdl.draw;
+        self.score_sq += wdl.score() * wdl.score();
+    }
+
+    // variance of the expected score over the node's visits
+    pub fn score_variance(&self) -> f32 {
+        let visits = self.visits as f32;
+        let mean = (self.wins + self.draws / 2.0) / visits;
+        (self.score_sq / visits - mean * mean).max(0.0)
     }
 
     pub fn set_mate_dist(&mut self, mate_dist: Option<NonZeroI16>) {
@@ -193,25 +258,20 @@ fn softmax(vals: &mut ArrayVec<f32, 256>, max_val: f32) {
 }
 
 pub struct Half {
-    nodes: Vec<Node>,
+    nodes: LargeBuffer<Node>,
     used: u32,
 }
 
 impl Half {
-    pub fn new(nodes: u64) -> Self {
-        let mut result = Self {
-            nodes: Vec::new(),
+    pub fn new(nodes: u64, huge_pages: bool) -> Self {
+        Self {
+            nodes: LargeBuffer::new(nodes as usize, huge_pages),
             used: 0,
-        };
-        result.nodes.reserve_exact(nodes as usize);
-        result
-            .nodes
-            .resize(nodes as usize, Node::new(Move::NULL, 0.0));
-        result
+        }
     }
 
     pub fn max_nodes(&self) -> u32 {
-        self.nodes.capacity() as u32
+        self.nodes.len() as u32
     }
 
     pub fn used_nodes(&self) -> u32 {
@@ -219,7 +279,7 @@ impl Half {
     }
 
     fn clear_indices(&mut self, half: u8) {
-        for node in &mut self.nodes {
+        for node in &mut self.nodes[..self.used as usize] {
             // node's children were not copied across, clear its children to be reexpanded
             if node.first_child_idx.half() != half {
                 node.first_child_idx = NodeIndex::NULL;
@@ -239,17 +299,24 @@ pub struct Tree {
 }
 
 impl Tree {
-    pub fn new(mb: u64) -> Self {
+    pub fn new(mb: u64, huge_pages: bool) -> Self {
         let total_nodes = mb * 1024 * 1024 / std::mem::size_of::<Node>() as u64;
         let half_nodes = total_nodes / 2;
         let mut result = Self {
-            halves: [Half::new(half_nodes), Half::new(half_nodes)],
+            halves: [
+                Half::new(half_nodes, huge_pages),
+                Half::new(half_nodes, huge_pages),
+            ],
             active_half: 0,
         };
         result.clear();
         result
     }
 
+    pub fn alloc_strategy(&self) -> AllocStrategy {
+        self.halves[0].nodes.strategy()
+    }
+
     pub fn curr_half(&self) -> &Half {
         &self.halves[self.active_half as usize]
     }
@@ -273,9 +340,12 @@ impl Tree {
         self[root] = Node::new(Move::NULL, 0.0);
     }
 
-    pub fn flip(&mut self) {
+    // switches to the other half, eagerly copying the most visited nodes across so most of the
+    // search survives, returns the number of nodes kept and the number the old half held
+    pub fn flip(&mut self) -> (u32, u32) {
         let old_root = self.root_node();
         let half = self.active_half;
+        let old_used = self.curr_half().used_nodes();
         self.curr_half_mut().clear_indices(half);
 
         self.active_half ^= 1;
@@ -283,6 +353,27 @@ impl Tree {
         let new_root = self.alloc_nodes(1).unwrap();
         assert!(new_root == self.root_node());
         self.copy_node_across(old_root, new_root);
+
+        // best first by visits, nodes whose children don't fit keep pointing into the old half
+        // and are fetched lazily as before
+        let budget = (self.curr_half().max_nodes() as f64 * FLIP_RETAIN_FRACTION) as u32;
+        let mut queue = BinaryHeap::new();
+        queue.push((self[new_root].visits, new_root.0));
+        while let Some((_, node_idx)) = queue.pop() {
+            let node_idx = NodeIndex(node_idx);
+            let child_count = self[node_idx].child_count();
+            if child_count == 0 || self.curr_half().used_nodes() + child_count > budget {
+                continue;
+            }
+            self.fetch_children(node_idx).unwrap();
+            for child_idx in self[node_idx].child_indices() {
+                if self[child_idx].child_count > 0 {
+                    queue.push((self[child_idx].visits, child_idx.0));
+                }
+            }
+        }
+
+        (self.curr_half().used_nodes(), old_used)
     }
 
     pub fn set_as_root(&mut self, node_idx: NodeIndex) {
@@ -329,13 +420,24 @@ impl Tree {
         policies
     }
 
-    pub fn expand_node(&mut self, node_idx: NodeIndex, board: &Board) -> Option<()> {
+    // root_pst is the softmax temperature used for the root's policy, a non empty search_moves
+    // keeps only those moves as children
+    pub fn expand_node(
+        &mut self,
+        node_idx: NodeIndex,
+        board: &Board,
+        root_pst: f32,
+        search_moves: &[Move],
+    ) -> Option<()> {
         let mut moves = MoveList::new();
         movegen::movegen(board, &mut moves);
+        if !search_moves.is_empty() {
+            moves.retain(|mv| search_moves.contains(mv));
+        }
 
         let first_child_idx = self.alloc_nodes(moves.len() as u32)?;
 
-        let pst = if node_idx.index() == 0 { 3.0 } else { 1.0 };
+        let pst = if node_idx.index() == 0 { root_pst } else { 1.0 };
 
         let policies = Self::compute_policies(board, moves.iter().map(|&mv| mv), pst);
 
@@ -351,9 +453,9 @@ impl Tree {
         Some(())
     }
 
-    pub fn relabel_policies(&mut self, node_idx: NodeIndex, board: &Board) {
+    pub fn relabel_policies(&mut self, node_idx: NodeIndex, board: &Board, root_pst: f32) {
         let pst = if node_idx == self.root_node() {
-            3.0
+            root_pst
         } else {
             1.0
         };
@@ -393,6 +495,205 @@ impl Tree {
         self.curr_half_mut().used += count;
         Some(NodeIndex::new(self.active_half, index))
     }
+
+    // the root position and both halves, so a later load can pick up the search where it stopped
+    pub fn save(&self, root_position: &Position) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(TREE_MAGIC);
+        bytes.extend_from_slice(&TREE_VERSION.to_le_bytes());
+
+        let fen = root_position.board().to_fen();
+        bytes.extend_from_slice(&(fen.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(fen.as_bytes());
+        bytes.extend_from_slice(&(root_position.keys().len() as u32).to_le_bytes());
+        for key in root_position.keys() {
+            bytes.extend_from_slice(&key.value().to_le_bytes());
+        }
+
+        bytes.push(self.active_half);
+        for half in &self.halves {
+            bytes.extend_from_slice(&half.used.to_le_bytes());
+            for node in &half.nodes[..half.used as usize] {
+                let mate_dist = node.mate_dist.map_or(0, |dist| dist.get());
+                bytes.extend_from_slice(&node.first_child_idx.0.to_le_bytes());
+                bytes.push(node.child_count);
+                bytes.extend_from_slice(&node.parent_move.raw().to_le_bytes());
+                bytes.push(node.result as u8);
+                bytes.extend_from_slice(&mate_dist.to_le_bytes());
+                bytes.push(node.lower as u8);
+                bytes.push(node.upper as u8);
+                bytes.extend_from_slice(&node.policy.to_le_bytes());
+                bytes.extend_from_slice(&node.wins.to_le_bytes());
+                bytes.extend_from_slice(&node.draws.to_le_bytes());
+                bytes.extend_from_slice(&node.score_sq.to_le_bytes());
+                bytes.extend_from_slice(&node.visits.to_le_bytes());
+            }
+        }
+
+        let checksum = fnv1a(&bytes);
+        bytes.extend_from_slice(&checksum.to_le_bytes());
+        bytes
+    }
+
+    // the tree is left untouched unless the whole file is valid
+    pub fn load(&mut self, bytes: &[u8]) -> Result<Position, String> {
+        if bytes.len() < TREE_MAGIC.len() + 8 || !bytes.starts_with(TREE_MAGIC) {
+            return Err("not a tree file".to_owned());
+        }
+        let (contents, checksum) = bytes.split_at(bytes.len() - 8);
+        if fnv1a(contents) != u64::from_le_bytes(checksum.try_into().unwrap()) {
+            return Err("checksum mismatch".to_owned());
+        }
+
+        let mut reader = ByteReader::new(&contents[TREE_MAGIC.len()..]);
+        let version = reader.u32()?;
+        if version != TREE_VERSION {
+            return Err(format!("unsupported tree file version {}", version));
+        }
+
+        let fen_len = reader.u32()? as usize;
+        let fen = std::str::from_utf8(reader.take(fen_len)?).map_err(|_| "invalid fen")?;
+        let board = Board::from_fen(fen).ok_or_else(|| format!("invalid fen {}", fen))?;
+        let key_count = reader.u32()? as usize;
+        let mut keys = Vec::with_capacity(key_count.max(512));
+        for _ in 0..key_count {
+            keys.push(ZobristKey::from_value(reader.u64()?));
+        }
+
+        let active_half = reader.u8()?;
+        if active_half > 1 {
+            return Err("invalid active half".to_owned());
+        }
+
+        let mut halves = [Vec::new(), Vec::new()];
+        for (i, nodes) in halves.iter_mut().enumerate() {
+            let used = reader.u32()?;
+            if used > self.halves[i].max_nodes() {
+                return Err(format!(
+                    "tree has {} nodes per half but only {} fit, increase Hash",
+                    used,
+                    self.halves[i].max_nodes()
+                ));
+            }
+            for _ in 0..used {
+                let first_child_idx = NodeIndex(reader.u32()?);
+                let child_count = reader.u8()?;
+                let parent_move = Move::from_raw(reader.u16()?);
+                let result = GameResult::from_raw(reader.u8()?).ok_or("invalid game result")?;
+                let mate_dist = NonZeroI16::new(reader.i16()?);
+                let lower = reader.u8()? as i8;
+                let upper = reader.u8()? as i8;
+                if !(-1..=1).contains(&lower) || !(lower..=1).contains(&upper) {
+                    return Err("invalid node bounds".to_owned());
+                }
+                nodes.push(Node {
+                    first_child_idx,
+                    child_count,
+                    parent_move,
+                    result,
+                    mate_dist,
+                    lower,
+                    upper,
+                    policy: reader.f32()?,
+                    wins: reader.f32()?,
+                    draws: reader.f32()?,
+                    score_sq: reader.f32()?,
+                    visits: reader.u32()?,
+                });
+            }
+        }
+        if !reader.is_empty() {
+            return Err("trailing data in tree file".to_owned());
+        }
+        if halves[active_half as usize].is_empty() {
+            return Err("tree has no root node".to_owned());
+        }
+
+        // every child range has to lie within the used part of its half
+        for node in halves.iter().flatten() {
+            if node.first_child_idx == NodeIndex::NULL {
+                if node.child_count != 0 {
+                    return Err("node has children but no child index".to_owned());
+                }
+                continue;
+            }
+            let used = halves[node.first_child_idx.half() as usize].len() as u32;
+            if node.first_child_idx.index() + node.child_count as u32 > used {
+                return Err("child index out of range".to_owned());
+            }
+        }
+
+        for (half, nodes) in self.halves.iter_mut().zip(halves) {
+            half.used = nodes.len() as u32;
+            for (i, node) in nodes.into_iter().enumerate() {
+                half.nodes[i] = node;
+            }
+        }
+        self.active_half = active_half;
+
+        Ok(Position::from_parts(board, keys))
+    }
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash = 0xcbf29ce484222325u64;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, pos: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos == self.bytes.len()
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.bytes.len() - self.pos < len {
+            return Err("unexpected end of tree file".to_owned());
+        }
+        let slice = &self.bytes[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(slice)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    fn i16(&mut self) -> Result<i16, String> {
+        Ok(i16::from_le_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    fn f32(&mut self) -> Result<f32, String> {
+        Ok(f32::from_le_bytes(self.array()?))
+    }
 }
 
 impl Index<NodeIndex> for Tree {
@@ -0,0 +1,129 @@
+use std::{fs, str::SplitWhitespace};
+
+use crate::search::{TreeRow, MCTS};
+
+// tree [depth] [min-visits] [dot|json <file>] [moves <move>...]
+pub fn run_tree_command(searcher: &MCTS, tokens: &mut SplitWhitespace) {
+    let mut numbers = Vec::new();
+    let mut export = None;
+    let mut moves = Vec::new();
+    while let Some(token) = tokens.next() {
+        match token {
+            "dot" | "json" => {
+                let Some(filename) = tokens.next() else {
+                    println!("info string missing filename");
+                    return;
+                };
+                export = Some((token, filename));
+            }
+            "moves" => moves.extend(tokens.by_ref()),
+            _ => match token.parse::<u32>() {
+                Ok(number) => numbers.push(number),
+                Err(_) => {
+                    println!("info string invalid tree argument {}", token);
+                    return;
+                }
+            },
+        }
+    }
+    let depth = numbers.first().copied().unwrap_or(1);
+    let min_visits = numbers.get(1).copied().unwrap_or(0);
+
+    let rows = match searcher.explore_tree(&moves, depth, min_visits) {
+        Ok(rows) => rows,
+        Err(err) => {
+            println!("info string {}", err);
+            return;
+        }
+    };
+
+    match export {
+        Some((format, filename)) => {
+            let contents = if format == "dot" {
+                to_dot(&rows)
+            } else {
+                to_json(&rows)
+            };
+            if let Err(err) = fs::write(filename, contents) {
+                println!("info string cannot write {}: {}", filename, err);
+            }
+        }
+        None => print_rows(&rows),
+    }
+}
+
+fn print_rows(rows: &[TreeRow]) {
+    for row in rows {
+        println!(
+            "{}{} visits {} ({:.1}%) q {:.3} p {:.3} uct {:.3} score {}{}{}",
+            "    ".repeat(row.depth as usize),
+            row.san,
+            row.visits,
+            row.share * 100.0,
+            row.q,
+            row.policy,
+            row.uct,
+            row.score,
+            if row.proven { " proven" } else { "" },
+            if row.inactive { " inactive" } else { "" }
+        );
+    }
+}
+
+fn to_dot(rows: &[TreeRow]) -> String {
+    let mut dot = String::from("digraph tree {\n    root [label=\"root\"];\n");
+    for (i, row) in rows.iter().enumerate() {
+        // proven nodes are drawn bold and nodes in the inactive half dashed
+        let mut style = Vec::new();
+        if row.proven {
+            style.push("bold");
+        }
+        if row.inactive {
+            style.push("dashed");
+        }
+        dot += format!(
+            "    n{} [label=\"{}\\n{} visits\\nq {:.3} p {:.3}\\n{}\" style=\"{}\"];\n",
+            i,
+            row.san,
+            row.visits,
+            row.q,
+            row.policy,
+            row.score.uci_str(),
+            style.join(",")
+        )
+        .as_str();
+        let parent = row
+            .parent
+            .map_or("root".to_owned(), |parent| format!("n{}", parent));
+        dot += format!("    {} -> n{};\n", parent, i).as_str();
+    }
+    dot += "}\n";
+    dot
+}
+
+fn json_children(rows: &[TreeRow], parent: Option<usize>) -> String {
+    let children = rows
+        .iter()
+        .enumerate()
+        .filter(|(_, row)| row.parent == parent)
+        .map(|(i, row)| {
+            format!(
+                "{{\"move\":\"{}\",\"visits\":{},\"q\":{:.4},\"policy\":{:.4},\"uct\":{:.4},\"score\":\"{}\",\"proven\":{},\"inactive\":{},\"children\":{}}}",
+                row.san,
+                row.visits,
+                row.q,
+                row.policy,
+                row.uct,
+                row.score.uci_str(),
+                row.proven,
+                row.inactive,
+                json_children(rows, Some(i))
+            )
+        })
+        .collect::<Vec<String>>();
+    format!("[{}]", children.join(","))
+}
+
+fn to_json(rows: &[TreeRow]) -> String {
+    format!("{{\"children\":{}}}\n", json_children(rows, None))
+}
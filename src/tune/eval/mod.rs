@@ -1,21 +1,51 @@
-use std::fs::File;
+use crate::chess::Board;
 
 mod data;
+mod report;
 mod trace;
 mod tune;
 
-pub fn main(filenames: &[String]) {
-    let mut files = Vec::with_capacity(filenames.len());
-    for filename in filenames {
-        files.push(File::open(filename).expect("Unable to open value data file"));
+// number of positions held in memory at once
+const BUFFER_SIZE: usize = 1 << 20;
+// weight of the game result in the training target, the rest goes to the search score
+const DEFAULT_LAMBDA: f32 = 0.0;
+
+pub fn main(args: &[String]) {
+    let mut lambda = DEFAULT_LAMBDA;
+    let mut filenames = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--lambda" {
+            lambda = args
+                .next()
+                .and_then(|value| value.parse::<f32>().ok())
+                .filter(|value| (0.0..=1.0).contains(value))
+                .expect("--lambda expects a value between 0 and 1");
+        } else {
+            filenames.push(arg.clone());
+        }
     }
+    println!("Lambda: {}", lambda);
 
-    let dataset = data::load_dataset(files.as_slice());
+    let mut loader = data::data_loader(&filenames, BUFFER_SIZE);
     let params = &trace::zero_params();
     println!("{}", trace::EvalFeature::format_all_features(params));
     println!(
         "Draw eval error: {}",
-        tune::error_total(params, &dataset, 400.0)
+        tune::error_total(params, loader.sample(), 400.0, lambda)
     );
-    tune::optimize(params.clone(), &dataset);
+    tune::optimize(params.clone(), &mut loader, lambda);
+}
+
+pub fn evaltrace_main(args: &[String]) {
+    let fen = args.join(" ");
+    let Some(board) = Board::from_fen(fen.as_str()) else {
+        println!("invalid fen");
+        return;
+    };
+    report::print_eval_trace(&board);
+}
+
+pub fn evalstats_main(filenames: &[String]) {
+    report::print_dataset_report(filenames);
 }
@@ -0,0 +1,416 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    ops::{Add, AddAssign, Div, Mul, Neg, Sub, SubAssign},
+};
+
+use crate::{
+    chess::Board,
+    eval::{self, EvalParams, EvalScorePairType, EvalScoreType, EvalValues},
+    tune::eval::trace::EvalFeature,
+    types::{Color, PieceType, Square},
+};
+
+const TERMS: usize = EvalFeature::TOTAL_FEATURES as usize;
+
+// the engine's eval split up by the term that produced each part of it, with COUNT it is instead
+// how often each term fired for either side since the two sides don't cancel out
+#[derive(Debug, Clone, PartialEq)]
+struct TermScores<const COUNT: bool>([f32; TERMS]);
+
+impl<const COUNT: bool> TermScores<COUNT> {
+    fn single(feature: EvalFeature, value: f32) -> Self {
+        let mut result = Self::default();
+        result.0[feature as usize] = if COUNT { 1.0 } else { value };
+        result
+    }
+}
+
+impl<const COUNT: bool> Default for TermScores<COUNT> {
+    fn default() -> Self {
+        Self([0.0; TERMS])
+    }
+}
+
+impl<const COUNT: bool> AddAssign for TermScores<COUNT> {
+    fn add_assign(&mut self, rhs: Self) {
+        for (a, b) in self.0.iter_mut().zip(rhs.0) {
+            *a += b;
+        }
+    }
+}
+
+impl<const COUNT: bool> Add for TermScores<COUNT> {
+    type Output = Self;
+    fn add(mut self, rhs: Self) -> Self::Output {
+        self += rhs;
+        self
+    }
+}
+
+impl<const COUNT: bool> SubAssign for TermScores<COUNT> {
+    fn sub_assign(&mut self, rhs: Self) {
+        for (a, b) in self.0.iter_mut().zip(rhs.0) {
+            if COUNT {
+                *a += b;
+            } else {
+                *a -= b;
+            }
+        }
+    }
+}
+
+impl<const COUNT: bool> Sub for TermScores<COUNT> {
+    type Output = Self;
+    fn sub(mut self, rhs: Self) -> Self::Output {
+        self -= rhs;
+        self
+    }
+}
+
+impl<const COUNT: bool> Neg for TermScores<COUNT> {
+    type Output = Self;
+    fn neg(mut self) -> Self::Output {
+        if !COUNT {
+            for a in self.0.iter_mut() {
+                *a = -*a;
+            }
+        }
+        self
+    }
+}
+
+impl<const COUNT: bool> Mul<i32> for TermScores<COUNT> {
+    type Output = Self;
+    fn mul(mut self, rhs: i32) -> Self::Output {
+        for a in self.0.iter_mut() {
+            *a *= rhs as f32;
+        }
+        self
+    }
+}
+
+impl<const COUNT: bool> Div<i32> for TermScores<COUNT> {
+    type Output = Self;
+    fn div(mut self, rhs: i32) -> Self::Output {
+        for a in self.0.iter_mut() {
+            *a /= rhs as f32;
+        }
+        self
+    }
+}
+
+impl<const COUNT: bool> EvalScoreType for TermScores<COUNT> {}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+struct TermPairs<const COUNT: bool> {
+    mg: TermScores<COUNT>,
+    eg: TermScores<COUNT>,
+}
+
+impl<const COUNT: bool> TermPairs<COUNT> {
+    fn single(feature: EvalFeature, value: eval::ScorePair) -> Self {
+        Self {
+            mg: TermScores::single(feature, value.mg() as f32),
+            eg: TermScores::single(feature, value.eg() as f32),
+        }
+    }
+}
+
+impl<const COUNT: bool> EvalScorePairType for TermPairs<COUNT> {
+    type ScoreType = TermScores<COUNT>;
+
+    fn mg(&self) -> Self::ScoreType {
+        self.mg.clone()
+    }
+
+    fn eg(&self) -> Self::ScoreType {
+        self.eg.clone()
+    }
+}
+
+impl<const COUNT: bool> AddAssign for TermPairs<COUNT> {
+    fn add_assign(&mut self, rhs: Self) {
+        self.mg += rhs.mg;
+        self.eg += rhs.eg;
+    }
+}
+
+impl<const COUNT: bool> Add for TermPairs<COUNT> {
+    type Output = Self;
+    fn add(mut self, rhs: Self) -> Self::Output {
+        self += rhs;
+        self
+    }
+}
+
+impl<const COUNT: bool> SubAssign for TermPairs<COUNT> {
+    fn sub_assign(&mut self, rhs: Self) {
+        self.mg -= rhs.mg;
+        self.eg -= rhs.eg;
+    }
+}
+
+impl<const COUNT: bool> Sub for TermPairs<COUNT> {
+    type Output = Self;
+    fn sub(mut self, rhs: Self) -> Self::Output {
+        self -= rhs;
+        self
+    }
+}
+
+impl<const COUNT: bool> Neg for TermPairs<COUNT> {
+    type Output = Self;
+    fn neg(self) -> Self::Output {
+        Self {
+            mg: -self.mg,
+            eg: -self.eg,
+        }
+    }
+}
+
+impl<const COUNT: bool> Mul<i32> for TermPairs<COUNT> {
+    type Output = Self;
+    fn mul(self, rhs: i32) -> Self::Output {
+        Self {
+            mg: self.mg * rhs,
+            eg: self.eg * rhs,
+        }
+    }
+}
+
+struct EvalTermTrace<const COUNT: bool> {}
+
+impl<const COUNT: bool> EvalValues for EvalTermTrace<COUNT> {
+    type ScoreType = TermScores<COUNT>;
+    type ScorePairType = TermPairs<COUNT>;
+
+    fn material(pt: PieceType) -> Self::ScorePairType {
+        TermPairs::single(EvalFeature::Material, EvalParams::material(pt))
+    }
+
+    fn psqt(c: Color, pt: PieceType, sq: Square) -> Self::ScorePairType {
+        TermPairs::single(EvalFeature::Psqt, EvalParams::psqt(c, pt, sq))
+    }
+
+    fn mobility(pt: PieceType, mob: u32) -> Self::ScorePairType {
+        TermPairs::single(EvalFeature::Mobility, EvalParams::mobility(pt, mob))
+    }
+
+    fn passed_pawn(rank: u8) -> Self::ScorePairType {
+        TermPairs::single(EvalFeature::PassedPawn, EvalParams::passed_pawn(rank))
+    }
+
+    fn our_passer_dist(dist: i32) -> Self::ScorePairType {
+        TermPairs::single(
+            EvalFeature::OurPasserDist,
+            EvalParams::our_passer_dist(dist),
+        )
+    }
+
+    fn their_passer_dist(dist: i32) -> Self::ScorePairType {
+        TermPairs::single(
+            EvalFeature::TheirPasserDist,
+            EvalParams::their_passer_dist(dist),
+        )
+    }
+
+    fn passed_blocked(rank: u8) -> Self::ScorePairType {
+        TermPairs::single(EvalFeature::PassedBlocked, EvalParams::passed_blocked(rank))
+    }
+
+    fn passed_safe_adv(rank: u8) -> Self::ScorePairType {
+        TermPairs::single(
+            EvalFeature::PassedSafeAdv,
+            EvalParams::passed_safe_adv(rank),
+        )
+    }
+
+    fn pawn_phalanx(rank: u8) -> Self::ScorePairType {
+        TermPairs::single(EvalFeature::PawnPhalanx, EvalParams::pawn_phalanx(rank))
+    }
+
+    fn defended_pawn(rank: u8) -> Self::ScorePairType {
+        TermPairs::single(EvalFeature::DefendedPawn, EvalParams::defended_pawn(rank))
+    }
+
+    fn safe_knight_check() -> Self::ScorePairType {
+        TermPairs::single(
+            EvalFeature::SafeKnightCheck,
+            EvalParams::safe_knight_check(),
+        )
+    }
+
+    fn safe_bishop_check() -> Self::ScorePairType {
+        TermPairs::single(
+            EvalFeature::SafeBishopCheck,
+            EvalParams::safe_bishop_check(),
+        )
+    }
+
+    fn safe_rook_check() -> Self::ScorePairType {
+        TermPairs::single(EvalFeature::SafeRookCheck, EvalParams::safe_rook_check())
+    }
+
+    fn safe_queen_check() -> Self::ScorePairType {
+        TermPairs::single(EvalFeature::SafeQueenCheck, EvalParams::safe_queen_check())
+    }
+
+    fn king_attacker_weight(pt: PieceType) -> Self::ScorePairType {
+        TermPairs::single(
+            EvalFeature::KingAttackerWeight,
+            EvalParams::king_attacker_weight(pt),
+        )
+    }
+
+    fn king_attacks(attacks: u32) -> Self::ScorePairType {
+        TermPairs::single(EvalFeature::KingAttacks, EvalParams::king_attacks(attacks))
+    }
+
+    fn pawn_shield(edge_dist: u8, rank: u8) -> Self::ScorePairType {
+        TermPairs::single(
+            EvalFeature::PawnShield,
+            EvalParams::pawn_shield(edge_dist, rank),
+        )
+    }
+
+    fn pawn_storm(edge_dist: u8, rank: u8) -> Self::ScorePairType {
+        TermPairs::single(
+            EvalFeature::PawnStorm,
+            EvalParams::pawn_storm(edge_dist, rank),
+        )
+    }
+
+    fn threat_by_pawn(stm: bool, pt: PieceType) -> Self::ScorePairType {
+        TermPairs::single(
+            EvalFeature::ThreatByPawn,
+            EvalParams::threat_by_pawn(stm, pt),
+        )
+    }
+
+    fn threat_by_knight(stm: bool, pt: PieceType, defended: bool) -> Self::ScorePairType {
+        TermPairs::single(
+            EvalFeature::ThreatByKnight,
+            EvalParams::threat_by_knight(stm, pt, defended),
+        )
+    }
+
+    fn threat_by_bishop(stm: bool, pt: PieceType, defended: bool) -> Self::ScorePairType {
+        TermPairs::single(
+            EvalFeature::ThreatByBishop,
+            EvalParams::threat_by_bishop(stm, pt, defended),
+        )
+    }
+
+    fn threat_by_rook(stm: bool, pt: PieceType, defended: bool) -> Self::ScorePairType {
+        TermPairs::single(
+            EvalFeature::ThreatByRook,
+            EvalParams::threat_by_rook(stm, pt, defended),
+        )
+    }
+
+    fn threat_by_queen(stm: bool, pt: PieceType, defended: bool) -> Self::ScorePairType {
+        TermPairs::single(
+            EvalFeature::ThreatByQueen,
+            EvalParams::threat_by_queen(stm, pt, defended),
+        )
+    }
+
+    fn push_threat(stm: bool) -> Self::ScorePairType {
+        TermPairs::single(EvalFeature::PushThreat, EvalParams::push_threat(stm))
+    }
+
+    fn tempo() -> Self::ScoreType {
+        TermScores::single(EvalFeature::Tempo, EvalParams::tempo() as f32)
+    }
+}
+
+struct TermBreakdown {
+    mg: TermScores<false>,
+    eg: TermScores<false>,
+    tapered: TermScores<false>,
+    phase: i32,
+}
+
+fn term_breakdown(board: &Board) -> TermBreakdown {
+    let (eval, phase) = eval::eval_pair_impl::<EvalTermTrace<false>>(board);
+    let tapered =
+        (eval.mg() * phase + eval.eg() * (24 - phase)) / 24 + EvalTermTrace::<false>::tempo();
+    TermBreakdown {
+        mg: eval.mg,
+        eg: eval.eg,
+        tapered,
+        phase,
+    }
+}
+
+pub fn print_eval_trace(board: &Board) {
+    let breakdown = term_breakdown(board);
+    println!("phase: {}/24", breakdown.phase);
+    println!("{:<20} {:>8} {:>8} {:>10}", "term", "mg", "eg", "tapered");
+    for feature in EvalFeature::iter() {
+        let idx = feature as usize;
+        println!(
+            "{:<20} {:>8.0} {:>8.0} {:>10.2}",
+            format!("{:?}", feature),
+            breakdown.mg.0[idx],
+            breakdown.eg.0[idx],
+            breakdown.tapered.0[idx]
+        );
+    }
+    println!(
+        "total: {:.2} (engine eval {})",
+        breakdown.tapered.0.iter().sum::<f32>(),
+        eval::eval(board)
+    );
+}
+
+// activation frequency and average contribution of every term over a value dataset
+pub fn print_dataset_report(filenames: &[String]) {
+    let mut positions = 0u64;
+    let mut activations = [0u64; TERMS];
+    let mut total = [0f64; TERMS];
+    let mut total_abs = [0f64; TERMS];
+
+    for filename in filenames {
+        let file = File::open(filename).expect("Unable to open value data file");
+        for line in BufReader::new(file).lines() {
+            let line = line.expect("Cannot read file");
+            let fen = line.split(" | ").next().unwrap();
+            let board = Board::from_fen(fen).expect("Invalid fen string in value data");
+
+            // a term is active if it fires for either side, even when both sides cancel out
+            let (counts, _) = eval::eval_pair_impl::<EvalTermTrace<true>>(&board);
+            let counts = counts.mg + EvalTermTrace::<true>::tempo();
+            let breakdown = term_breakdown(&board);
+            for i in 0..TERMS {
+                activations[i] += (counts.0[i] != 0.0) as u64;
+                total[i] += breakdown.tapered.0[i] as f64;
+                total_abs[i] += breakdown.tapered.0[i].abs() as f64;
+            }
+
+            positions += 1;
+            if positions.is_multiple_of(65536) {
+                println!("Processed {} positions", positions);
+            }
+        }
+    }
+
+    assert!(positions > 0, "No positions in data files");
+
+    println!("{} positions", positions);
+    println!(
+        "{:<20} {:>10} {:>10} {:>10}",
+        "term", "active %", "avg", "avg abs"
+    );
+    for feature in EvalFeature::iter() {
+        let idx = feature as usize;
+        println!(
+            "{:<20} {:>10.2} {:>10.2} {:>10.2}",
+            format!("{:?}", feature),
+            100.0 * activations[idx] as f64 / positions as f64,
+            total[idx] / positions as f64,
+            total_abs[idx] / positions as f64
+        );
+    }
+}
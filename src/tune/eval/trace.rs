@@ -2,7 +2,7 @@ use std::ops::{Add, AddAssign, Div, Mul, Neg, Sub, SubAssign};
 
 use crate::{
     chess::Board,
-    eval::{self, EvalScorePairType, EvalValues, ScorePair},
+    eval::{self, EvalScorePairType, EvalValues},
     tune::SparseTrace,
     types::{Color, PieceType, Square},
 };
@@ -149,7 +149,7 @@ impl EvalFeature {
         unsafe { std::mem::transmute(raw) }
     }
 
-    fn ft_cnt(self) -> u32 {
+    pub fn ft_cnt(self) -> u32 {
         match self {
             Material => 2 * 6,
             Psqt => 2 * 6 * 64,
@@ -179,7 +179,7 @@ impl EvalFeature {
         }
     }
 
-    fn ft_offset(self) -> u32 {
+    pub fn ft_offset(self) -> u32 {
         let mut offset = 0;
         for i in 0..(self as u32) {
             offset += Self::ft_cnt(Self::from_raw(i));
@@ -187,11 +187,11 @@ impl EvalFeature {
         offset
     }
 
-    fn iter() -> impl Iterator<Item = Self> {
+    pub fn iter() -> impl Iterator<Item = Self> {
         (0..Self::TOTAL_FEATURES).map(|i| Self::from_raw(i))
     }
 
-    fn total_fts() -> u32 {
+    pub fn total_fts() -> u32 {
         let mut count = 0;
         for feature in Self::iter() {
             count += Self::ft_cnt(feature);
@@ -688,35 +688,6 @@ pub fn compute_coeffs(board: &Board) -> Vec<(u32, f32)> {
     result
 }
 
-// used for computing scale factor
-pub fn compute_default_material(board: &Board) -> i32 {
-    use PieceType::*;
-
-    const MATERIAL: [ScorePair; 6] = [
-        ScorePair::new(63, 119),
-        ScorePair::new(267, 337),
-        ScorePair::new(301, 360),
-        ScorePair::new(381, 631),
-        ScorePair::new(769, 1197),
-        ScorePair::new(0, 0),
-    ];
-
-    let stm = board.stm();
-
-    let material = MATERIAL[0] * (board.piece_count(stm, Pawn) - board.piece_count(!stm, Pawn))
-        + MATERIAL[1] * (board.piece_count(stm, Knight) - board.piece_count(!stm, Knight))
-        + MATERIAL[2] * (board.piece_count(stm, Bishop) - board.piece_count(!stm, Bishop))
-        + MATERIAL[3] * (board.piece_count(stm, Rook) - board.piece_count(!stm, Rook))
-        + MATERIAL[4] * (board.piece_count(stm, Queen) - board.piece_count(!stm, Queen));
-
-    let phase = (4 * board.pieces(Queen).popcount()
-        + 2 * board.pieces(Rook).popcount()
-        + board.pieces(Bishop).popcount()
-        + board.pieces(Knight).popcount()) as i32;
-
-    (material.mg() * phase.min(24) + material.eg() * (24 - phase.min(24))) / 24
-}
-
 pub fn zero_params() -> Vec<f32> {
     let mut result = Vec::new();
     for _ in 0..EvalFeature::total_fts() {
@@ -1,8 +1,8 @@
 use std::time::Instant;
 
-use crate::tune::eval::{
-    data::{Dataset, Position},
-    trace,
+use crate::tune::{
+    eval::{data::Position, trace},
+    loader::DataLoader,
 };
 
 fn eval_eval_cp(params: &Vec<f32>, pos: &Position) -> f32 {
@@ -18,61 +18,73 @@ fn eval_eval_wdl(params: &Vec<f32>, pos: &Position, scale: f32) -> f32 {
     return 1.0 / (1.0 + (-eval_eval_cp(params, pos) / scale).exp());
 }
 
-fn error_single(params: &Vec<f32>, pos: &Position, scale: f32) -> f32 {
+// blend of the search score and the game result, lambda = 1.0 trains purely on the result
+fn target(pos: &Position, lambda: f32) -> f32 {
+    lambda * pos.wdl + (1.0 - lambda) * pos.score
+}
+
+fn error_single(params: &Vec<f32>, pos: &Position, scale: f32, lambda: f32) -> f32 {
     let eval = eval_eval_wdl(params, pos, scale);
-    let wdl = pos.wdl;
-    return (eval - wdl) * (eval - wdl);
+    let target = target(pos, lambda);
+    (eval - target) * (eval - target)
 }
 
-pub fn error_total(params: &Vec<f32>, dataset: &Dataset, scale: f32) -> f32 {
+pub fn error_total(params: &Vec<f32>, positions: &[Position], scale: f32, lambda: f32) -> f32 {
     let mut total = 0.0;
-    for pos in &dataset.positions {
-        total += error_single(params, pos, scale);
+    for pos in positions {
+        total += error_single(params, pos, scale, lambda);
     }
-    total / dataset.positions.len() as f32
+    total / positions.len() as f32
 }
 
-fn material_error(dataset: &Dataset, k: f32) -> f32 {
+pub fn engine_error(positions: &[Position], scale: f32, lambda: f32) -> f32 {
     let mut total = 0.0;
-    for pos in &dataset.positions {
-        let target = pos.score;
-        let material = 1.0 / (1.0 + (-pos.default_material as f32 * k).exp());
-        total += (material - target) * (material - target);
+    for pos in positions {
+        let eval = 1.0 / (1.0 + (-pos.engine_eval as f32 / scale).exp());
+        let target = target(pos, lambda);
+        total += (eval - target) * (eval - target);
     }
-    total / dataset.positions.len() as f32
+    total / positions.len() as f32
 }
 
-pub fn compute_eval_scale(dataset: &Dataset) -> f32 {
-    let mut best_k = 1f32 / 200f32;
-    let mut best_error = 100f32;
-    let mut start = 0f32;
-    let mut end = 1f32 / 50f32;
-    let mut step = 1f32 / 400f32;
-
-    for _ in 0..7 {
-        println!("{} {} {}", start, end, step);
-        let mut curr_k = start + step;
-        while curr_k < end + step {
-            let error = material_error(dataset, curr_k);
-            if error < best_error {
-                best_error = error;
-                best_k = curr_k;
-                println!("New best: {}, error: {}", best_k, best_error);
-            }
-            curr_k += step;
+// golden section search for the scale that best fits the engine's current eval to the targets
+pub fn compute_eval_scale(positions: &[Position], lambda: f32) -> f32 {
+    const INV_PHI: f32 = 0.618034;
+    let mut lo = 50f32;
+    let mut hi = 2000f32;
+    let mut a = hi - INV_PHI * (hi - lo);
+    let mut b = lo + INV_PHI * (hi - lo);
+    let mut error_a = engine_error(positions, a, lambda);
+    let mut error_b = engine_error(positions, b, lambda);
+
+    while hi - lo > 0.1 {
+        if error_a < error_b {
+            hi = b;
+            b = a;
+            error_b = error_a;
+            a = hi - INV_PHI * (hi - lo);
+            error_a = engine_error(positions, a, lambda);
+        } else {
+            lo = a;
+            a = b;
+            error_a = error_b;
+            b = lo + INV_PHI * (hi - lo);
+            error_b = engine_error(positions, b, lambda);
         }
-
-        start = best_k - step;
-        end = best_k + step;
-        step *= 0.1;
     }
 
-    1.0 / best_k
+    (lo + hi) / 2.0
 }
 
-pub fn compute_single_grad(params: &Vec<f32>, grads: &mut Vec<f32>, pos: &Position, scale: f32) {
+pub fn compute_single_grad(
+    params: &Vec<f32>,
+    grads: &mut Vec<f32>,
+    pos: &Position,
+    scale: f32,
+    lambda: f32,
+) {
     let eval = eval_eval_wdl(params, pos, scale);
-    let target = pos.score;
+    let target = target(pos, lambda);
     let grad_base = (eval - target) * eval * (1.0 - eval);
 
     for coeff in &pos.coeffs {
@@ -80,16 +92,22 @@ pub fn compute_single_grad(params: &Vec<f32>, grads: &mut Vec<f32>, pos: &Positi
     }
 }
 
-pub fn compute_grads(params: &Vec<f32>, grads: &mut Vec<f32>, positions: &[Position], scale: f32) {
+pub fn compute_grads(
+    params: &Vec<f32>,
+    grads: &mut Vec<f32>,
+    positions: &[Position],
+    scale: f32,
+    lambda: f32,
+) {
     for pos in positions {
-        compute_single_grad(params, grads, pos, scale);
+        compute_single_grad(params, grads, pos, scale, lambda);
     }
     for grad in grads {
         *grad /= scale * positions.len() as f32;
     }
 }
 
-pub fn optimize(mut params: Vec<f32>, dataset: &Dataset) {
+pub fn optimize(mut params: Vec<f32>, loader: &mut DataLoader<Position>, lambda: f32) {
     const BETA1: f32 = 0.9;
     const BETA2: f32 = 0.999;
     const EPSILON: f32 = 1e-8;
@@ -99,8 +117,13 @@ pub fn optimize(mut params: Vec<f32>, dataset: &Dataset) {
     // let BATCH_SIZE: u32 = dataset.positions.len() as u32;
     const SUPERBATCH_SIZE: u32 = 1000;
 
-    let eval_scale = compute_eval_scale(dataset);
-    println!("Eval scale: {}", eval_scale);
+    // the scale is fit on the first chunk rather than the whole dataset
+    let eval_scale = compute_eval_scale(loader.sample(), lambda);
+    println!(
+        "Eval scale: {}, engine eval error: {}",
+        eval_scale,
+        engine_error(loader.sample(), eval_scale, lambda)
+    );
 
     let mut grads = params.clone();
     let mut velocity = params.clone();
@@ -109,23 +132,12 @@ pub fn optimize(mut params: Vec<f32>, dataset: &Dataset) {
     velocity.fill(0.0);
     momentum.fill(0.0);
 
-    let mut batch_idx = 0;
     let mut num_batches = 0;
     let start_time = Instant::now();
     loop {
-        let begin_idx = batch_idx * BATCH_SIZE as usize;
-        let end_idx = (batch_idx + 1) * BATCH_SIZE as usize;
-        if end_idx > dataset.positions.len() {
-            batch_idx = 0;
-            continue;
-        }
+        let batch = loader.next_batch(BATCH_SIZE as usize);
         grads.fill(0.0);
-        compute_grads(
-            &params,
-            &mut grads,
-            &dataset.positions[begin_idx..end_idx],
-            eval_scale,
-        );
+        compute_grads(&params, &mut grads, batch, eval_scale, lambda);
         // compare_slow_fast(&params, dataset);
         // println!("{:?}", &grads[0..5]);
         for i in 0..params.len() {
@@ -133,14 +145,15 @@ pub fn optimize(mut params: Vec<f32>, dataset: &Dataset) {
             velocity[i] = BETA2 * velocity[i] + (1.0 - BETA2) * grads[i] * grads[i];
             params[i] -= LR * momentum[i] / (velocity[i].sqrt() + EPSILON);
         }
-        batch_idx += 1;
         num_batches += 1;
 
+        // errors are measured on the loaded chunk, the full dataset may not fit in memory
         if num_batches % 100 == 0 {
             println!(
-                "Batch {} error {}, batches/s: {}",
+                "Batch {} epoch {} error {}, batches/s: {}",
                 num_batches,
-                error_total(&params, dataset, eval_scale),
+                loader.epoch(),
+                error_total(&params, loader.sample(), eval_scale, lambda),
                 num_batches as f32 / start_time.elapsed().as_secs_f32()
             );
         }
@@ -149,7 +162,7 @@ pub fn optimize(mut params: Vec<f32>, dataset: &Dataset) {
             println!(
                 "SuperBatch {} error {}",
                 num_batches / SUPERBATCH_SIZE,
-                error_total(&params, dataset, eval_scale)
+                error_total(&params, loader.sample(), eval_scale, lambda)
             );
             println!("{}", trace::EvalFeature::format_all_features(&params));
         }
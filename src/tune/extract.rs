@@ -0,0 +1,194 @@
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Write},
+};
+
+use rand::Rng;
+use rand_core::{RngCore, SeedableRng};
+use rand_xorshift::XorShiftRng;
+
+use crate::{
+    chess::movegen::{self, MoveList},
+    pgn::{ParsedGame, PgnReader},
+    search::{SearchLimits, MCTS},
+    selfplay::Outcome,
+    types::Color,
+};
+
+struct ExtractConfig {
+    rate: f64,
+    skip_plies: usize,
+    nodes: i32,
+    visits: i32,
+    out: String,
+}
+
+struct Writers {
+    value: BufWriter<File>,
+    policy: BufWriter<File>,
+}
+
+fn wdl(outcome: Outcome) -> f32 {
+    match outcome {
+        Outcome::WhiteWin => 1.0,
+        Outcome::Draw => 0.5,
+        Outcome::BlackWin => 0.0,
+    }
+}
+
+// writes the sampled positions of a game and returns how many were written
+fn extract_game(
+    game: &ParsedGame,
+    config: &ExtractConfig,
+    searcher: &mut MCTS,
+    rng: &mut XorShiftRng,
+    writers: &mut Writers,
+) -> u32 {
+    let Some(outcome) = game.result else {
+        return 0;
+    };
+    let result = wdl(outcome);
+
+    searcher.new_game();
+    let mut limits = SearchLimits::new();
+    if config.nodes > 0 {
+        limits.max_nodes = config.nodes;
+    }
+    if config.visits > 0 {
+        limits.max_visits = config.visits;
+    }
+
+    let mut written = 0;
+    let mut pos = game.start.clone();
+    for (ply, mv) in game.moves.iter().enumerate() {
+        if ply >= config.skip_plies && rng.random_bool(config.rate) {
+            let fen = pos.board().to_fen();
+            let mut moves = MoveList::new();
+            movegen::movegen(pos.board(), &mut moves);
+
+            // without rescoring the score is the result and the policy target is the move played
+            let (score, visit_dist) = if config.nodes > 0 || config.visits > 0 {
+                let results = searcher.run(&limits, false, &pos);
+                let mut score = results.score.win_prob();
+                if pos.board().stm() == Color::Black {
+                    score = 1.0 - score;
+                }
+                let dist = results.visit_dist.iter().map(|(_, frac)| *frac).collect();
+                (score, dist)
+            } else {
+                let dist = moves
+                    .iter()
+                    .map(|candidate| if candidate == mv { 1.0 } else { 0.0 })
+                    .collect::<Vec<f32>>();
+                (result, dist)
+            };
+
+            writeln!(writers.value, "{} | {} | {}", fen, score, result)
+                .expect("Unable to write value data");
+            if visit_dist.len() == moves.len() {
+                let mut line = fen;
+                for frac in visit_dist {
+                    line += format!(" | {}", frac).as_str();
+                }
+                writeln!(writers.policy, "{}", line).expect("Unable to write policy data");
+            }
+            written += 1;
+        }
+        pos.make_move(*mv);
+    }
+    written
+}
+
+pub fn main(args: &[String]) {
+    let mut config = ExtractConfig {
+        rate: 0.25,
+        skip_plies: 8,
+        nodes: 0,
+        visits: 0,
+        out: "extract".to_owned(),
+    };
+    let mut filenames = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--rate" => {
+                config.rate = args
+                    .next()
+                    .and_then(|value| value.parse::<f64>().ok())
+                    .filter(|value| (0.0..=1.0).contains(value))
+                    .expect("--rate expects a value between 0 and 1")
+            }
+            "--skip" => {
+                config.skip_plies = args
+                    .next()
+                    .and_then(|value| value.parse().ok())
+                    .expect("--skip expects a number of plies")
+            }
+            "--nodes" => {
+                config.nodes = args
+                    .next()
+                    .and_then(|value| value.parse().ok())
+                    .expect("--nodes expects a node count")
+            }
+            "--visits" => {
+                config.visits = args
+                    .next()
+                    .and_then(|value| value.parse().ok())
+                    .expect("--visits expects a playout count")
+            }
+            "--out" => {
+                config.out = args
+                    .next()
+                    .expect("--out expects a filename prefix")
+                    .clone()
+            }
+            _ => filenames.push(arg.clone()),
+        }
+    }
+
+    let seed = rand::rng().next_u64();
+    println!("RNG seed: {}", seed);
+    let mut rng = XorShiftRng::seed_from_u64(seed);
+    let mut searcher = MCTS::new();
+
+    let value_filename = format!("{}.value.txt", config.out);
+    let policy_filename = format!("{}.policy.txt", config.out);
+    let mut writers = Writers {
+        value: BufWriter::new(
+            File::create(&value_filename).expect("Unable to create value data file"),
+        ),
+        policy: BufWriter::new(
+            File::create(&policy_filename).expect("Unable to create policy data file"),
+        ),
+    };
+
+    let mut games = 0;
+    let mut skipped = 0;
+    let mut positions = 0;
+    for filename in &filenames {
+        let file = File::open(filename).expect("Unable to open pgn file");
+        for game in PgnReader::new(BufReader::new(file)) {
+            match game {
+                Ok(game) => {
+                    positions +=
+                        extract_game(&game, &config, &mut searcher, &mut rng, &mut writers);
+                    games += 1;
+                    if games % 1000 == 0 {
+                        println!("Processed {} games, {} positions", games, positions);
+                    }
+                }
+                Err(err) => {
+                    println!("Skipping game in {}: {}", filename, err);
+                    skipped += 1;
+                }
+            }
+        }
+    }
+
+    writers.value.flush().expect("Unable to write value data");
+    writers.policy.flush().expect("Unable to write policy data");
+    println!(
+        "Extracted {} positions from {} games ({} skipped) to {} and {}",
+        positions, games, skipped, value_filename, policy_filename
+    );
+}
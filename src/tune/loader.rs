@@ -0,0 +1,118 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Lines},
+};
+
+use rand::seq::SliceRandom;
+
+// streams positions from the data files in shuffled chunks of at most buffer_size positions,
+// so memory use stays bounded no matter how large the files are
+pub struct DataLoader<T> {
+    filenames: Vec<String>,
+    file_idx: usize,
+    lines: Option<Lines<BufReader<File>>>,
+    parse: fn(&str) -> Option<T>,
+    buffer: Vec<T>,
+    buffer_size: usize,
+    buffer_pos: usize,
+    epoch: u32,
+    epoch_positions: u64,
+}
+
+impl<T> DataLoader<T> {
+    pub fn new(filenames: &[String], buffer_size: usize, parse: fn(&str) -> Option<T>) -> Self {
+        assert!(!filenames.is_empty(), "No data files given");
+        for filename in filenames {
+            File::open(filename).expect("Unable to open data file");
+        }
+        Self {
+            filenames: filenames.to_vec(),
+            file_idx: 0,
+            lines: None,
+            parse,
+            buffer: Vec::with_capacity(buffer_size),
+            buffer_size,
+            buffer_pos: 0,
+            epoch: 0,
+            epoch_positions: 0,
+        }
+    }
+
+    pub fn epoch(&self) -> u32 {
+        self.epoch
+    }
+
+    // the currently loaded chunk, used for things like fitting the eval scale
+    pub fn sample(&mut self) -> &[T] {
+        if self.buffer.is_empty() {
+            self.refill();
+        }
+        &self.buffer
+    }
+
+    pub fn next_batch(&mut self, batch_size: usize) -> &[T] {
+        // the leftover tail of a chunk is dropped unless the chunk is smaller than a batch
+        let remaining = self.buffer.len() - self.buffer_pos;
+        if remaining == 0 || (remaining < batch_size && self.buffer_pos > 0) {
+            self.refill();
+        }
+        let begin = self.buffer_pos;
+        let end = (begin + batch_size).min(self.buffer.len());
+        self.buffer_pos = end;
+        &self.buffer[begin..end]
+    }
+
+    fn next_line(&mut self) -> Option<String> {
+        loop {
+            if self.lines.is_none() {
+                let file =
+                    File::open(&self.filenames[self.file_idx]).expect("Unable to open data file");
+                self.lines = Some(BufReader::new(file).lines());
+            }
+
+            if let Some(line) = self.lines.as_mut().unwrap().next() {
+                return Some(line.expect("Cannot read file"));
+            }
+
+            self.lines = None;
+            self.file_idx += 1;
+            if self.file_idx == self.filenames.len() {
+                self.file_idx = 0;
+                return None;
+            }
+        }
+    }
+
+    fn refill(&mut self) {
+        self.buffer.clear();
+        self.buffer_pos = 0;
+
+        while self.buffer.len() < self.buffer_size {
+            let Some(line) = self.next_line() else {
+                // end of the dataset, don't let a chunk straddle two epochs
+                assert!(self.epoch_positions > 0, "No positions in data files");
+                println!(
+                    "Finished epoch {} with {} positions",
+                    self.epoch, self.epoch_positions
+                );
+                self.epoch += 1;
+                self.epoch_positions = 0;
+                if self.buffer.is_empty() {
+                    continue;
+                }
+                break;
+            };
+
+            if let Some(pos) = (self.parse)(line.as_str()) {
+                self.buffer.push(pos);
+                self.epoch_positions += 1;
+            }
+        }
+
+        self.buffer.shuffle(&mut rand::rng());
+        println!(
+            "Loaded and shuffled chunk of {} positions",
+            self.buffer.len()
+        );
+    }
+}
@@ -6,6 +6,8 @@ use std::{
 use crate::{eval::EvalScoreType, policy::PolicyScoreType};
 
 pub mod eval;
+pub mod extract;
+mod loader;
 pub mod policy;
 
 #[derive(Debug, Default, Clone, PartialEq)]
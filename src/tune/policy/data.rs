@@ -1,17 +1,10 @@
-use std::{
-    fs::File,
-    io::{BufRead, BufReader},
-};
-
-use rand::seq::SliceRandom;
-
 use crate::{
     chess::{
         movegen::{self, MoveList},
         Board,
     },
     policy,
-    tune::policy::trace,
+    tune::{loader::DataLoader, policy::trace},
 };
 
 pub struct Coefficient {
@@ -26,69 +19,44 @@ pub struct Position {
     pub movecount: u8,
 }
 
-pub struct Dataset {
-    pub positions: Vec<Position>,
+pub fn data_loader(filenames: &[String], buffer_size: usize) -> DataLoader<Position> {
+    DataLoader::new(filenames, buffer_size, parse_position)
 }
 
-pub fn load_dataset(files: &[File]) -> Dataset {
-    let mut positions = Vec::new();
-    for file in files {
-        load_data_file(&file, &mut positions);
-    }
-    positions.shuffle(&mut rand::rng());
-    println!("Finished shuffling positions");
-    Dataset {
-        positions: positions,
+fn parse_position(line: &str) -> Option<Position> {
+    let parts: Vec<&str> = line.split(" | ").collect();
+    let fen = parts[0];
+    let board = Board::from_fen(fen).expect("Invalid fen string in policy data");
+    let mut pos = Position {
+        coeffs: Vec::new(),
+        visit_dist: Vec::with_capacity(parts.len() - 1),
+        movecount: 0,
+    };
+
+    for str in parts.iter().skip(1) {
+        let frac = str
+            .parse::<f32>()
+            .expect("Could not parse visit distribution");
+        pos.visit_dist.push(frac);
     }
-}
-
-fn load_data_file(file: &File, positions: &mut Vec<Position>) {
-    let reader = BufReader::new(file);
-    let lines = reader
-        .lines()
-        .collect::<Result<Vec<String>, _>>()
-        .expect("Cannot read file");
 
-    for line in lines {
-        let parts: Vec<&str> = line.split(" | ").collect();
-        let fen = parts[0];
-        let board = Board::from_fen(fen).expect("Invalid fen string in policy data");
-        let mut pos = Position {
-            coeffs: Vec::new(),
-            visit_dist: Vec::with_capacity(parts.len() - 1),
-            movecount: 0,
-        };
+    let mut moves = MoveList::new();
+    movegen::movegen(&board, &mut moves);
 
-        for str in parts.iter().skip(1) {
-            let frac = str
-                .parse::<f32>()
-                .expect("Could not parse visit distribution");
-            pos.visit_dist.push(frac);
-        }
+    pos.movecount = moves.len() as u8;
 
-        let mut moves = MoveList::new();
-        movegen::movegen(&board, &mut moves);
+    let data = policy::PolicyData::new(&board);
 
-        pos.movecount = moves.len() as u8;
-
-        let data = policy::PolicyData::new(&board);
-
-        for (mv_idx, mv) in moves.iter().enumerate() {
-            let coeffs = trace::compute_coeffs(&board, *mv, &data);
-            for c in coeffs {
-                pos.coeffs.push(Coefficient {
-                    mv_idx: mv_idx as u16,
-                    index: c.0 as u16,
-                    value: c.1,
-                });
-            }
-        }
-
-        positions.push(pos);
-
-        if positions.len() % 65536 == 0 {
-            println!("Loaded {} positions", positions.len());
+    for (mv_idx, mv) in moves.iter().enumerate() {
+        let coeffs = trace::compute_coeffs(&board, *mv, &data);
+        for c in coeffs {
+            pos.coeffs.push(Coefficient {
+                mv_idx: mv_idx as u16,
+                index: c.0 as u16,
+                value: c.1,
+            });
         }
     }
-    println!("Finished loading {} positions", positions.len());
+
+    Some(pos)
 }
@@ -1,20 +1,16 @@
-use std::fs::File;
-
 mod data;
 mod trace;
 mod tune;
 
-pub fn main(filenames: &[String]) {
-    let mut files = Vec::with_capacity(filenames.len());
-    for filename in filenames {
-        files.push(File::open(filename).expect("Unable to open policy data file"));
-    }
+// number of positions held in memory at once
+const BUFFER_SIZE: usize = 1 << 19;
 
-    let dataset = data::load_dataset(files.as_slice());
+pub fn main(filenames: &[String]) {
+    let mut loader = data::data_loader(filenames, BUFFER_SIZE);
     let params = &trace::zero_params();
     println!(
         "Uniform policy error: {}",
-        tune::error_total(params, &dataset)
+        tune::error_total(params, loader.sample())
     );
-    tune::optimize(params.clone(), &dataset);
+    tune::optimize(params.clone(), &mut loader);
 }
@@ -2,9 +2,9 @@ use std::time::Instant;
 
 use arrayvec::ArrayVec;
 
-use crate::tune::policy::{
-    data::{Dataset, Position},
-    trace,
+use crate::tune::{
+    loader::DataLoader,
+    policy::{data::Position, trace},
 };
 
 fn eval_policy(params: &Vec<f32>, pos: &Position) -> ArrayVec<f32, 256> {
@@ -45,12 +45,12 @@ fn error_single(params: &Vec<f32>, pos: &Position) -> f32 {
     loss
 }
 
-pub fn error_total(params: &Vec<f32>, dataset: &Dataset) -> f32 {
+pub fn error_total(params: &Vec<f32>, positions: &[Position]) -> f32 {
     let mut total = 0.0;
-    for pos in &dataset.positions {
+    for pos in positions {
         total += error_single(params, pos);
     }
-    total / dataset.positions.len() as f32
+    total / positions.len() as f32
 }
 
 pub fn compute_single_grad(params: &Vec<f32>, grads: &mut Vec<f32>, pos: &Position) {
@@ -73,7 +73,7 @@ pub fn compute_grads(params: &Vec<f32>, grads: &mut Vec<f32>, positions: &[Posit
     }
 }
 
-pub fn optimize(mut params: Vec<f32>, dataset: &Dataset) {
+pub fn optimize(mut params: Vec<f32>, loader: &mut DataLoader<Position>) {
     const BETA1: f32 = 0.9;
     const BETA2: f32 = 0.999;
     const EPSILON: f32 = 1e-8;
@@ -88,32 +88,27 @@ pub fn optimize(mut params: Vec<f32>, dataset: &Dataset) {
     velocity.fill(0.0);
     momentum.fill(0.0);
 
-    let mut batch_idx = 0;
     let mut num_batches = 0;
     let start_time = Instant::now();
     loop {
-        let begin_idx = batch_idx * BATCH_SIZE as usize;
-        let end_idx = (batch_idx + 1) * BATCH_SIZE as usize;
-        if end_idx > dataset.positions.len() {
-            batch_idx = 0;
-            continue;
-        }
+        let batch = loader.next_batch(BATCH_SIZE as usize);
         grads.fill(0.0);
-        compute_grads(&params, &mut grads, &dataset.positions[begin_idx..end_idx]);
+        compute_grads(&params, &mut grads, batch);
         // compare_slow_fast(&params, dataset);
         for i in 0..params.len() {
             momentum[i] = BETA1 * momentum[i] + (1.0 - BETA1) * grads[i];
             velocity[i] = BETA2 * velocity[i] + (1.0 - BETA2) * grads[i] * grads[i];
             params[i] -= LR * momentum[i] / (velocity[i].sqrt() + EPSILON);
         }
-        batch_idx += 1;
         num_batches += 1;
 
+        // errors are measured on the loaded chunk, the full dataset may not fit in memory
         if num_batches % 100 == 0 {
             println!(
-                "Batch {} error {}, batches/s: {}",
+                "Batch {} epoch {} error {}, batches/s: {}",
                 num_batches,
-                error_total(&params, dataset),
+                loader.epoch(),
+                error_total(&params, loader.sample()),
                 num_batches as f32 / start_time.elapsed().as_secs_f32()
             );
         }
@@ -122,7 +117,7 @@ pub fn optimize(mut params: Vec<f32>, dataset: &Dataset) {
             println!(
                 "SuperBatch {} error {}",
                 num_batches / SUPERBATCH_SIZE,
-                error_total(&params, dataset)
+                error_total(&params, loader.sample())
             );
             println!("{}", trace::PolicyFeature::format_all_features(&params));
         }